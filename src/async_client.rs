@@ -0,0 +1,500 @@
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+
+/// The tokio flavour of [`Client`](crate::Client): requests are futures and
+/// notifications a [`Stream`](futures_core::Stream), so no executor thread ever
+/// blocks on the server's pipes.
+pub struct AsyncClient {
+    process: std::sync::Arc<std::sync::Mutex<crate::process::ServerProcess>>,
+    req_to_ra: std::sync::Arc<tokio::sync::Mutex<tokio::process::ChildStdin>>,
+    router: std::sync::Arc<std::sync::Mutex<crate::router::Router>>,
+    req_id: crate::protocol::ReqId,
+    default_timeout: Option<std::time::Duration>,
+    profile: std::sync::Arc<dyn crate::ServerProfile>,
+    shutdown_grace: std::time::Duration,
+    capabilities: Option<crate::Capabilities>,
+    server_capabilities: std::sync::Mutex<Option<crate::capabilities::ServerCapabilities>>,
+    /// set by [`AsyncClient::set_transcript`], shared with the reader task
+    transcript: Transcript,
+}
+
+type Transcript = std::sync::Arc<std::sync::Mutex<Option<crate::transcript::Recorder>>>;
+
+/// Server notifications delivered to an [`AsyncClient`] subscriber.
+pub struct NotificationStream(tokio::sync::mpsc::UnboundedReceiver<lsp_server::Notification>);
+
+impl futures_core::Stream for NotificationStream {
+    type Item = lsp_server::Notification;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+impl AsyncClient {
+    /// Spawns the language server with piped stdin/stdout and starts the task
+    /// reading its messages. Must be called within a tokio runtime. The server
+    /// is killed when the client is dropped without [`AsyncClient::shutdown`].
+    pub fn spawn(command: tokio::process::Command) -> crate::Result<Self> {
+        Self::spawn_with_stderr(command, crate::StderrPolicy::default())
+    }
+
+    /// Spawns the server `profile` describes and talks to it accordingly.
+    pub fn spawn_profile(profile: impl crate::ServerProfile + 'static) -> crate::Result<Self> {
+        let mut client = Self::spawn_with_stderr(profile.command()?.into(), profile.stderr())?;
+        client.set_profile(profile);
+        Ok(client)
+    }
+
+    /// Like [`AsyncClient::spawn`], with the server's stderr going where `stderr` says.
+    pub fn spawn_with_stderr(
+        mut command: tokio::process::Command,
+        stderr: crate::StderrPolicy,
+    ) -> crate::Result<Self> {
+        // the same child process as the blocking client's, killed when dropped,
+        // only its pipes are driven by tokio
+        let (process, stdin, stdout) =
+            crate::process::ServerProcess::spawn(command.as_std_mut(), &stderr)?;
+        let process = std::sync::Arc::new(std::sync::Mutex::new(process));
+        let stdin = tokio::process::ChildStdin::from_std(stdin)?;
+        let stdout = tokio::process::ChildStdout::from_std(stdout)?;
+        let client = Self {
+            process: process.clone(),
+            req_to_ra: std::sync::Arc::new(tokio::sync::Mutex::new(stdin)),
+            router: std::sync::Arc::default(),
+            req_id: crate::protocol::ReqId::new(),
+            default_timeout: None,
+            profile: std::sync::Arc::new(crate::RustAnalyzerProfile::default()),
+            shutdown_grace: crate::process::DEFAULT_SHUTDOWN_GRACE,
+            capabilities: None,
+            server_capabilities: std::sync::Mutex::new(None),
+            transcript: Transcript::default(),
+        };
+        tokio::spawn(read_loop(
+            tokio::io::BufReader::new(stdout),
+            client.req_to_ra.clone(),
+            client.router.clone(),
+            process,
+            client.transcript.clone(),
+        ));
+        Ok(client)
+    }
+
+    /// Sets the timeout applied to every request that is not given one explicitly.
+    /// `None`, the default, waits forever.
+    pub fn set_default_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.default_timeout = timeout;
+    }
+
+    /// Sets the kind of server talked to, see [`Client::set_profile`](crate::Client::set_profile).
+    pub fn set_profile(&mut self, profile: impl crate::ServerProfile + 'static) {
+        self.profile = std::sync::Arc::new(profile);
+    }
+
+    /// Replaces the rust-analyzer settings used by the next `initialize`,
+    /// by default only `checkOnSave` is disabled.
+    pub fn set_ra_config(&mut self, ra_config: crate::RaConfig) {
+        self.set_profile(crate::RustAnalyzerProfile::default().ra_config(ra_config));
+    }
+
+    /// Sets how long [`AsyncClient::shutdown`] waits for the server to exit, and
+    /// again after SIGTERM, before killing it. 5 seconds by default.
+    pub fn set_shutdown_grace(&mut self, grace: std::time::Duration) {
+        self.shutdown_grace = grace;
+    }
+
+    /// Records every message sent and received from now on to `transcript`,
+    /// see [`Client::set_transcript`](crate::Client::set_transcript).
+    pub fn set_transcript(&mut self, transcript: impl std::io::Write + Send + 'static) {
+        *self.transcript.lock().unwrap() = Some(crate::transcript::Recorder::new(transcript));
+    }
+
+    /// Replaces the client capabilities declared by the next `initialize`,
+    /// see [`Client::set_capabilities`](crate::Client::set_capabilities).
+    pub fn set_capabilities(&mut self, capabilities: crate::Capabilities) {
+        self.capabilities = Some(capabilities);
+    }
+
+    /// The capabilities the server announced in `initialize`, `None` before it answered.
+    pub fn server_capabilities(&self) -> Option<lsp_types::ServerCapabilities> {
+        let server = self.server_capabilities.lock().unwrap();
+        server
+            .as_ref()
+            .map(|server| server.result.capabilities.clone())
+    }
+
+    /// The server's name and version from its `initialize` response.
+    pub fn server_info(&self) -> Option<lsp_types::ServerInfo> {
+        let server = self.server_capabilities.lock().unwrap();
+        server
+            .as_ref()
+            .and_then(|server| server.result.server_info.clone())
+    }
+
+    /// The position encoding the server chose, UTF-16 unless it picked another one.
+    pub fn position_encoding(&self) -> crate::PositionEncoding {
+        let server = self.server_capabilities.lock().unwrap();
+        server
+            .as_ref()
+            .map(|server| server.position_encoding())
+            .unwrap_or(crate::PositionEncoding::Utf16)
+    }
+
+    /// Whether the server announced the capability request `method` needs,
+    /// see [`Client::supports`](crate::Client::supports).
+    pub fn supports(&self, method: &str) -> bool {
+        let server = self.server_capabilities.lock().unwrap();
+        server.as_ref().is_none_or(|server| server.supports(method))
+    }
+
+    /// Performs the `initialize`/`initialized` handshake and waits until
+    /// the server finished loading the workspace.
+    pub async fn init(&self, workspace: &crate::Workspace) -> crate::Result<()> {
+        self.initialize(workspace).await?;
+        self.wait_ready(crate::protocol::DEFAULT_READY_DEADLINE, |_| {})
+            .await
+    }
+
+    /// Performs the `initialize`/`initialized` handshake only.
+    pub async fn initialize(&self, workspace: &crate::Workspace) -> crate::Result<()> {
+        let init_req = crate::protocol::initialize(
+            self.req_id.inc(),
+            workspace,
+            &self.profile,
+            self.capabilities.as_ref(),
+            &mut self.router.lock().unwrap(),
+        )?;
+        let result =
+            crate::protocol::result_value(self.call(init_req, self.default_timeout).await?)?
+                .unwrap_or_default();
+        *self.server_capabilities.lock().unwrap() =
+            Some(crate::capabilities::ServerCapabilities::parse(result)?);
+        self.notify::<lsp_types::notification::Initialized>(lsp_types::InitializedParams {})
+            .await
+    }
+
+    /// Waits until the server finished loading the workspace, see
+    /// [`Client::wait_ready`](crate::Client::wait_ready).
+    pub async fn wait_ready<F>(
+        &self,
+        deadline: std::time::Duration,
+        mut on_progress: F,
+    ) -> crate::Result<()>
+    where
+        F: FnMut(&crate::Progress),
+    {
+        let (event_tx, mut event_rx) = tokio::sync::mpsc::unbounded_channel();
+        let ready = self
+            .router
+            .lock()
+            .unwrap()
+            .watch_progress(Box::new(move |event| event_tx.send(event.clone()).is_ok()))?;
+        if ready {
+            return Ok(());
+        }
+        let wait = async {
+            while let Some(event) = event_rx.recv().await {
+                if event.ready(&mut on_progress) {
+                    return Ok(());
+                }
+            }
+            Err(crate::ClientError::ServerExited)
+        };
+        tokio::time::timeout(deadline, wait)
+            .await
+            .unwrap_or(Err(crate::ClientError::Timeout))
+    }
+
+    /// Sends request `R` and awaits its typed result.
+    pub async fn request<R: Request>(&self, params: R::Params) -> crate::Result<R::Result> {
+        let req = crate::protocol::request::<R>(self.req_id.inc(), params)?;
+        crate::protocol::result::<R>(self.call(req, self.default_timeout).await?)
+    }
+
+    /// Like [`AsyncClient::request`] but cancels the request with `$/cancelRequest`
+    /// after `timeout`, returning [`ClientError::Timeout`](crate::ClientError::Timeout).
+    pub async fn request_timeout<R: Request>(
+        &self,
+        params: R::Params,
+        timeout: std::time::Duration,
+    ) -> crate::Result<R::Result> {
+        let req = crate::protocol::request::<R>(self.req_id.inc(), params)?;
+        crate::protocol::result::<R>(self.call(req, Some(timeout)).await?)
+    }
+
+    /// Sends notification `N`.
+    pub async fn notify<N: Notification>(&self, params: N::Params) -> crate::Result<()> {
+        self.write(crate::protocol::notification::<N>(params)?.into())
+            .await
+    }
+
+    /// Streams server notifications of `method`.
+    pub fn subscribe(&self, method: &str) -> NotificationStream {
+        self.subscribe_method(Some(method.to_string()))
+    }
+
+    /// Streams every server notification.
+    pub fn notifications(&self) -> NotificationStream {
+        self.subscribe_method(None)
+    }
+
+    fn subscribe_method(&self, method: Option<String>) -> NotificationStream {
+        let (notif_tx, notif_rx) = tokio::sync::mpsc::unbounded_channel();
+        self.router.lock().unwrap().subscribe(
+            method,
+            Box::new(move |notif| notif_tx.send(notif.clone()).is_ok()),
+        );
+        NotificationStream(notif_rx)
+    }
+
+    /// Answers server-to-client requests of `method` with `handler` instead of the default reply.
+    /// It also replaces the `workspace/configuration` and `workspace/workspaceFolders`
+    /// answers taken from the profile and workspace, registered before `initialize` or after.
+    pub fn on_request<F>(&self, method: &str, handler: F)
+    where
+        F: FnMut(serde_json::Value) -> Result<serde_json::Value, lsp_server::ResponseError>
+            + Send
+            + 'static,
+    {
+        self.router
+            .lock()
+            .unwrap()
+            .on_request(method.to_string(), Box::new(handler));
+    }
+
+    /// The last stderr lines of the server, see [`Client::stderr_tail`](crate::Client::stderr_tail).
+    pub fn stderr_tail(&self) -> Vec<String> {
+        self.process.lock().unwrap().stderr_tail()
+    }
+
+    /// Sends `shutdown` and `exit`, then waits for the server process to terminate,
+    /// see [`Client::exit`](crate::Client::exit) for the grace periods.
+    pub async fn shutdown(self) -> crate::Result<std::process::ExitStatus> {
+        self.process.lock().unwrap().set_shutting_down();
+        let timeout = crate::protocol::shutdown_timeout(&*self.profile, self.default_timeout);
+        let req = crate::protocol::request::<lsp_types::request::Shutdown>(self.req_id.inc(), ())?;
+        let shutdown = match self.call(req, timeout).await {
+            Ok(rsp) => crate::protocol::result::<lsp_types::request::Shutdown>(rsp),
+            Err(err) => Err(err),
+        };
+        let exit = match crate::protocol::shutdown_result(&*self.profile, shutdown) {
+            Ok(()) => self.notify::<lsp_types::notification::Exit>(()).await,
+            Err(err) => Err(err),
+        };
+        let grace = self.shutdown_grace;
+        let process = self.process.clone();
+        tokio::task::spawn_blocking(move || process.lock().unwrap().finish(grace, exit))
+            .await
+            .map_err(|err| std::io::Error::other(err.to_string()))?
+    }
+
+    async fn call(
+        &self,
+        req: lsp_server::Request,
+        timeout: Option<std::time::Duration>,
+    ) -> crate::Result<lsp_server::Response> {
+        if !self.supports(&req.method) {
+            return Err(crate::ClientError::Unsupported(req.method));
+        }
+        let id = req.id.clone();
+        let (rsp_tx, rsp_rx) = tokio::sync::oneshot::channel();
+        let on_response = crate::trace::on_response(&req, move |rsp| {
+            let _ = rsp_tx.send(rsp);
+        });
+        self.router
+            .lock()
+            .unwrap()
+            .register(id.clone(), on_response, None)?;
+        self.write(lsp_server::Message::Request(req)).await?;
+        let rsp = match timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, rsp_rx).await {
+                Ok(rsp) => rsp,
+                Err(_elapsed) => {
+                    let cancel = self
+                        .router
+                        .lock()
+                        .unwrap()
+                        .cancel(&id, crate::ClientError::Timeout)?;
+                    if let Some(cancel) = cancel {
+                        self.write(cancel.into()).await?;
+                    }
+                    return Err(crate::ClientError::Timeout);
+                }
+            },
+            None => rsp_rx.await,
+        };
+        // the sender is only dropped without a value when the reader task is gone
+        rsp.unwrap_or(Err(crate::ClientError::ServerExited))
+    }
+
+    async fn write(&self, msg: lsp_server::Message) -> crate::Result<()> {
+        match write_message(&mut *self.req_to_ra.lock().await, &self.transcript, msg).await {
+            // the server is gone, tell why rather than failing with EPIPE
+            Err(crate::ClientError::Io(err)) if err.kind() == std::io::ErrorKind::BrokenPipe => {
+                Err(exit_error(self.process.clone()).await)
+            }
+            result => result,
+        }
+    }
+}
+
+impl Drop for AsyncClient {
+    /// The reader task outlives the client, the server is killed here unless it already exited.
+    fn drop(&mut self) {
+        let mut process = self.process.lock().unwrap_or_else(|err| err.into_inner());
+        process.kill();
+    }
+}
+
+/// See [`ServerProcess::unexpected_exit`](crate::process::ServerProcess::unexpected_exit),
+/// which blocks for a moment while the server's last stderr lines come in.
+async fn exit_error(
+    process: std::sync::Arc<std::sync::Mutex<crate::process::ServerProcess>>,
+) -> crate::ClientError {
+    let report =
+        tokio::task::spawn_blocking(move || process.lock().unwrap().unexpected_exit()).await;
+    crate::process::exit_error(&report.unwrap_or_default())
+}
+
+/// Writes `msg`, recorded while the caller holds the `req_to_ra` lock so the
+/// transcript is in wire order.
+async fn write_message(
+    req_to_ra: &mut tokio::process::ChildStdin,
+    transcript: &Transcript,
+    msg: lsp_server::Message,
+) -> crate::Result<()> {
+    use tokio::io::AsyncWriteExt;
+    crate::trace::message(crate::Direction::ToServer, &msg);
+    crate::transcript::record(transcript, crate::Direction::ToServer, &msg);
+    // lsp_server only frames into blocking writers, so frame into memory first
+    let mut frame = Vec::new();
+    msg.write(&mut frame)?;
+    req_to_ra.write_all(&frame).await?;
+    req_to_ra.flush().await?;
+    Ok(())
+}
+
+/// Reads the content of one `Content-Length` framed message, `None` at EOF.
+/// Header names are matched case-insensitively.
+async fn read_frame(
+    rsp_from_ra: &mut (impl tokio::io::AsyncBufRead + Unpin),
+) -> std::io::Result<Option<Vec<u8>>> {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+    let invalid_data = |msg: String| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+    let mut header = Vec::new();
+    let mut content_length = None;
+    loop {
+        header.clear();
+        if rsp_from_ra.read_until(b'\n', &mut header).await? == 0 {
+            return Ok(None);
+        }
+        let line = String::from_utf8_lossy(&header);
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(": ") {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                content_length = Some(
+                    value
+                        .parse::<usize>()
+                        .map_err(|err| invalid_data(err.to_string()))?,
+                );
+            }
+        }
+    }
+    let content_length =
+        content_length.ok_or_else(|| invalid_data("missing Content-Length".to_string()))?;
+    let mut content = vec![0; content_length];
+    rsp_from_ra.read_exact(&mut content).await?;
+    Ok(Some(content))
+}
+
+async fn read_loop(
+    mut rsp_from_ra: tokio::io::BufReader<tokio::process::ChildStdout>,
+    req_to_ra: std::sync::Arc<tokio::sync::Mutex<tokio::process::ChildStdin>>,
+    router: std::sync::Arc<std::sync::Mutex<crate::router::Router>>,
+    process: std::sync::Arc<std::sync::Mutex<crate::process::ServerProcess>>,
+    transcript: Transcript,
+) {
+    let failed = loop {
+        let msg = match read_frame(&mut rsp_from_ra).await {
+            // as an io error like `lsp_server::Message::read` would return
+            Ok(Some(content)) => serde_json::from_slice(&content).map_err(std::io::Error::from),
+            Ok(None) => break None,
+            Err(err) => Err(err),
+        };
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(err) if crate::protocol::is_eof(&err) => break None,
+            Err(err) => break Some(err),
+        };
+        crate::trace::message(crate::Direction::FromServer, &msg);
+        crate::transcript::record(&transcript, crate::Direction::FromServer, &msg);
+        let reply = router.lock().unwrap().route(msg);
+        if let Some(reply) = reply {
+            if write_message(&mut *req_to_ra.lock().await, &transcript, reply)
+                .await
+                .is_err()
+            {
+                break None;
+            }
+        }
+    };
+    match failed {
+        Some(err) => {
+            tracing::warn!("cannot read from the server: {err}");
+            router
+                .lock()
+                .unwrap()
+                .close(crate::protocol::read_error(err));
+        }
+        None => {
+            let report =
+                tokio::task::spawn_blocking(move || process.lock().unwrap().unexpected_exit())
+                    .await
+                    .unwrap_or_default();
+            router
+                .lock()
+                .unwrap()
+                .close(move || crate::process::exit_error(&report));
+        }
+    }
+}
+
+#[test]
+fn read_frames() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let frames = |mut stream: &'static [u8]| {
+        runtime.block_on(async move {
+            let mut frames = Vec::new();
+            loop {
+                match read_frame(&mut stream).await {
+                    Ok(Some(frame)) => frames.push(String::from_utf8(frame).unwrap()),
+                    Ok(None) => return Ok(frames),
+                    Err(err) => return Err(err.kind()),
+                }
+            }
+        })
+    };
+    let stream = b"Content-Length: 2\r\n\r\n{}content-length: 4\r\nContent-Type: application/vscode-jsonrpc; charset=utf-8\r\n\r\nnull";
+    let parsed = frames(stream).unwrap();
+    assert_eq!(parsed, ["{}", "null"]);
+    assert_eq!(
+        frames(b"Content-Type: text\r\n\r\n{}"),
+        Err(std::io::ErrorKind::InvalidData)
+    );
+    assert_eq!(
+        frames(b"Content-Length: x\r\n\r\n"),
+        Err(std::io::ErrorKind::InvalidData)
+    );
+    assert_eq!(
+        frames(b"Content-Length: 9\r\n\r\n{}"),
+        Err(std::io::ErrorKind::UnexpectedEof)
+    );
+}
@@ -0,0 +1,29 @@
+//! `lsp-mock SCRIPT [REPORT]` plays the [`MockServer`](lsp_client::mock::MockServer)
+//! saved as JSON in SCRIPT on stdio, then saves the
+//! [`MockReport`](lsp_client::mock::MockReport) as JSON to REPORT.
+//! Exits with 1 when the client strayed from the script.
+
+fn main() {
+    let mut args = std::env::args_os().skip(1);
+    let script = match args.next() {
+        Some(script) => script,
+        None => {
+            eprintln!("usage: lsp-mock SCRIPT [REPORT]");
+            std::process::exit(2);
+        }
+    };
+    let script = std::fs::read(&script).expect("cannot read the script");
+    let mock: lsp_client::mock::MockServer =
+        serde_json::from_slice(&script).expect("invalid script");
+    let report = mock.serve_stdio();
+    for error in &report.errors {
+        eprintln!("lsp-mock: {error}");
+    }
+    if let Some(path) = args.next() {
+        let json = serde_json::to_vec_pretty(&report).expect("cannot serialize the report");
+        std::fs::write(path, json).expect("cannot write the report");
+    }
+    if !report.errors.is_empty() {
+        std::process::exit(1);
+    }
+}
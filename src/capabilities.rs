@@ -0,0 +1,320 @@
+/// `general.positionEncodings` offered by the client, `positionEncoding` chosen by the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PositionEncoding {
+    Utf8,
+    /// the LSP default when the server does not choose
+    Utf16,
+    Utf32,
+}
+
+impl PositionEncoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf16 => "utf-16",
+            Self::Utf32 => "utf-32",
+        }
+    }
+
+    fn from_str(encoding: &str) -> Option<Self> {
+        match encoding {
+            "utf-8" => Some(Self::Utf8),
+            "utf-16" => Some(Self::Utf16),
+            "utf-32" => Some(Self::Utf32),
+            _ => None,
+        }
+    }
+}
+
+/// The `ClientCapabilities` sent in `initialize`.
+///
+/// Kept as JSON so LSP 3.17 fields lsp-types 0.93 does not model yet
+/// (`positionEncodings`, pull diagnostics) can be declared too.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    json: serde_json::Map<String, serde_json::Value>,
+}
+
+const SEMANTIC_TOKEN_TYPES: [&str; 22] = [
+    "namespace",
+    "type",
+    "class",
+    "enum",
+    "interface",
+    "struct",
+    "typeParameter",
+    "parameter",
+    "variable",
+    "property",
+    "enumMember",
+    "event",
+    "function",
+    "method",
+    "macro",
+    "keyword",
+    "modifier",
+    "comment",
+    "string",
+    "number",
+    "regexp",
+    "operator",
+];
+
+const SEMANTIC_TOKEN_MODIFIERS: [&str; 10] = [
+    "declaration",
+    "definition",
+    "readonly",
+    "static",
+    "deprecated",
+    "abstract",
+    "async",
+    "modification",
+    "documentation",
+    "defaultLibrary",
+];
+
+impl Default for Capabilities {
+    /// What a typical editor declares: hierarchical document symbols,
+    /// work-done progress, UTF-16 positions, snippets, pull diagnostics and
+    /// semantic tokens, plus rust-analyzer's `serverStatusNotification`.
+    fn default() -> Self {
+        Self::none()
+            .set("experimental.serverStatusNotification", true)
+            .hierarchical_document_symbols(true)
+            .work_done_progress(true)
+            .position_encodings(&[PositionEncoding::Utf16])
+            .snippet_support(true)
+            .pull_diagnostics(true)
+            .semantic_tokens(true)
+    }
+}
+
+impl Capabilities {
+    /// Declares nothing at all, every capability is opt-in.
+    pub fn none() -> Self {
+        Self {
+            json: serde_json::Map::new(),
+        }
+    }
+
+    /// `textDocument.documentSymbol.hierarchicalDocumentSymbolSupport`, so
+    /// `textDocument/documentSymbol` answers nested `DocumentSymbol`s with a `selectionRange`
+    pub fn hierarchical_document_symbols(self, enable: bool) -> Self {
+        self.set(
+            "textDocument.documentSymbol.hierarchicalDocumentSymbolSupport",
+            enable,
+        )
+    }
+
+    /// `window.workDoneProgress`, needed to see the server's loading `$/progress`
+    pub fn work_done_progress(self, enable: bool) -> Self {
+        self.set("window.workDoneProgress", enable)
+    }
+
+    /// `general.positionEncodings` in order of preference
+    pub fn position_encodings(self, encodings: &[PositionEncoding]) -> Self {
+        let encodings = encodings
+            .iter()
+            .map(|encoding| encoding.as_str())
+            .collect::<Vec<_>>();
+        self.set("general.positionEncodings", encodings)
+    }
+
+    /// `textDocument.completion.completionItem.snippetSupport`
+    pub fn snippet_support(self, enable: bool) -> Self {
+        self.set(
+            "textDocument.completion.completionItem.snippetSupport",
+            enable,
+        )
+    }
+
+    /// `textDocument.diagnostic`, the LSP 3.17 `textDocument/diagnostic` pull model
+    pub fn pull_diagnostics(self, enable: bool) -> Self {
+        if !enable {
+            return self.remove("textDocument.diagnostic");
+        }
+        self.set(
+            "textDocument.diagnostic",
+            serde_json::json!({ "dynamicRegistration": false, "relatedDocumentSupport": false }),
+        )
+    }
+
+    /// `textDocument.semanticTokens` with full and range requests
+    pub fn semantic_tokens(self, enable: bool) -> Self {
+        if !enable {
+            return self.remove("textDocument.semanticTokens");
+        }
+        self.set(
+            "textDocument.semanticTokens",
+            serde_json::json!({
+                "requests": { "full": { "delta": false }, "range": true },
+                "tokenTypes": SEMANTIC_TOKEN_TYPES,
+                "tokenModifiers": SEMANTIC_TOKEN_MODIFIERS,
+                "formats": ["relative"],
+                "multilineTokenSupport": false,
+                "overlappingTokenSupport": false
+            }),
+        )
+    }
+
+    /// Sets any capability by its dotted key, e.g. `set("workspace.configuration", true)`.
+    pub fn set(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        *crate::ra_config::entry(&mut self.json, key) = value.into();
+        self
+    }
+
+    fn remove(mut self, key: &str) -> Self {
+        let (parent, last) = key.rsplit_once('.').expect("nested capability key");
+        if let serde_json::Value::Object(parent) = crate::ra_config::entry(&mut self.json, parent) {
+            parent.remove(last);
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(self.json.clone())
+    }
+}
+
+impl From<lsp_types::ClientCapabilities> for Capabilities {
+    fn from(capabilities: lsp_types::ClientCapabilities) -> Self {
+        match serde_json::to_value(capabilities) {
+            Ok(serde_json::Value::Object(json)) => Self { json },
+            _ => Self::none(),
+        }
+    }
+}
+
+/// The server's `initialize` response, kept as JSON as well for LSP 3.17
+/// fields like `positionEncoding`.
+#[derive(Debug, Clone)]
+pub(crate) struct ServerCapabilities {
+    pub(crate) result: lsp_types::InitializeResult,
+    raw: serde_json::Value,
+}
+
+impl ServerCapabilities {
+    pub(crate) fn parse(result: serde_json::Value) -> crate::Result<Self> {
+        Ok(Self {
+            result: serde_json::from_value(result.clone())?,
+            raw: result,
+        })
+    }
+
+    pub(crate) fn position_encoding(&self) -> PositionEncoding {
+        self.raw
+            .pointer("/capabilities/positionEncoding")
+            .and_then(serde_json::Value::as_str)
+            .and_then(PositionEncoding::from_str)
+            .unwrap_or(PositionEncoding::Utf16)
+    }
+
+    /// How the server wants `textDocument/didChange`, incremental when it did not say.
+    pub(crate) fn sync_kind(&self) -> lsp_types::TextDocumentSyncKind {
+        match &self.result.capabilities.text_document_sync {
+            Some(lsp_types::TextDocumentSyncCapability::Kind(kind)) => *kind,
+            Some(lsp_types::TextDocumentSyncCapability::Options(options)) => options
+                .change
+                .unwrap_or(lsp_types::TextDocumentSyncKind::INCREMENTAL),
+            None => lsp_types::TextDocumentSyncKind::INCREMENTAL,
+        }
+    }
+
+    /// Whether `textDocument/didSave` should carry the saved text.
+    pub(crate) fn save_includes_text(&self) -> bool {
+        match &self.result.capabilities.text_document_sync {
+            Some(lsp_types::TextDocumentSyncCapability::Options(options)) => matches!(
+                options.save,
+                Some(lsp_types::TextDocumentSyncSaveOptions::SaveOptions(
+                    lsp_types::SaveOptions {
+                        include_text: Some(true)
+                    }
+                ))
+            ),
+            _ => false,
+        }
+    }
+
+    /// Whether the server announced the provider `method` needs. Methods without
+    /// a provider in the spec, like rust-analyzer's extensions, are assumed supported.
+    pub(crate) fn supports(&self, method: &str) -> bool {
+        let provider = match provider(method) {
+            Some(provider) => provider,
+            None => return true,
+        };
+        match self.raw.pointer(&format!("/capabilities/{provider}")) {
+            None | Some(serde_json::Value::Null) | Some(serde_json::Value::Bool(false)) => false,
+            Some(_) => true,
+        }
+    }
+}
+
+fn provider(method: &str) -> Option<&'static str> {
+    let provider = match method {
+        "textDocument/completion" => "completionProvider",
+        "textDocument/hover" => "hoverProvider",
+        "textDocument/signatureHelp" => "signatureHelpProvider",
+        "textDocument/declaration" => "declarationProvider",
+        "textDocument/definition" => "definitionProvider",
+        "textDocument/typeDefinition" => "typeDefinitionProvider",
+        "textDocument/implementation" => "implementationProvider",
+        "textDocument/references" => "referencesProvider",
+        "textDocument/documentHighlight" => "documentHighlightProvider",
+        "textDocument/documentSymbol" => "documentSymbolProvider",
+        "textDocument/codeAction" => "codeActionProvider",
+        "textDocument/codeLens" => "codeLensProvider",
+        "textDocument/documentLink" => "documentLinkProvider",
+        "textDocument/documentColor" => "colorProvider",
+        "textDocument/formatting" => "documentFormattingProvider",
+        "textDocument/rangeFormatting" => "documentRangeFormattingProvider",
+        "textDocument/onTypeFormatting" => "documentOnTypeFormattingProvider",
+        "textDocument/rename" => "renameProvider",
+        "textDocument/foldingRange" => "foldingRangeProvider",
+        "textDocument/selectionRange" => "selectionRangeProvider",
+        "textDocument/prepareCallHierarchy" => "callHierarchyProvider",
+        "textDocument/linkedEditingRange" => "linkedEditingRangeProvider",
+        "textDocument/moniker" => "monikerProvider",
+        "textDocument/inlayHint" => "inlayHintProvider",
+        "textDocument/diagnostic" | "workspace/diagnostic" => "diagnosticProvider",
+        "workspace/symbol" => "workspaceSymbolProvider",
+        "workspace/executeCommand" => "executeCommandProvider",
+        method if method.starts_with("textDocument/semanticTokens/") => "semanticTokensProvider",
+        _ => return None,
+    };
+    Some(provider)
+}
+
+#[test]
+fn server_capabilities_support() {
+    let caps = ServerCapabilities::parse(serde_json::json!({
+        "capabilities": {
+            "positionEncoding": "utf-8",
+            "referencesProvider": true,
+            "documentSymbolProvider": { "label": "rust-analyzer" },
+            "hoverProvider": false
+        },
+        "serverInfo": { "name": "rust-analyzer", "version": "0.0.0" }
+    }))
+    .unwrap();
+    assert_eq!(caps.position_encoding(), PositionEncoding::Utf8);
+    assert!(caps.supports("textDocument/references"));
+    assert!(caps.supports("textDocument/documentSymbol"));
+    assert!(!caps.supports("textDocument/hover"));
+    assert!(!caps.supports("textDocument/rename"));
+    assert!(caps.supports("rust-analyzer/analyzerStatus"));
+    assert_eq!(caps.result.server_info.unwrap().name, "rust-analyzer");
+
+    let client_caps = Capabilities::default()
+        .pull_diagnostics(false)
+        .position_encodings(&[PositionEncoding::Utf8, PositionEncoding::Utf16]);
+    let json = client_caps.to_json();
+    assert_eq!(
+        json["general"]["positionEncodings"],
+        serde_json::json!(["utf-8", "utf-16"])
+    );
+    assert!(json["textDocument"].get("diagnostic").is_none());
+    assert_eq!(
+        json["textDocument"]["documentSymbol"]["hierarchicalDocumentSymbolSupport"],
+        true
+    );
+}
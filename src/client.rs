@@ -0,0 +1,1240 @@
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+
+/// A blocking LSP client talking to a language server over its stdio pipes,
+/// a TCP or Unix domain socket, any pair of byte streams, or channels to a
+/// server running in the same process.
+///
+/// A background thread owns the stream from the server and routes every message,
+/// so requests can be pipelined with [`Client::send`] and awaited later.
+///
+/// A server started by [`Client::spawn`] is owned by the client and killed
+/// when the client is dropped without [`Client::exit`]. One started by
+/// [`Client::spawn_supervised`] is also restarted when it crashes.
+pub struct Client {
+    conn: std::sync::Arc<Connection>,
+    req_id: crate::protocol::ReqId,
+    default_timeout: Option<std::time::Duration>,
+    profile: std::sync::Arc<dyn crate::ServerProfile>,
+    shutdown_grace: std::time::Duration,
+    /// `None` declares the profile's capabilities
+    capabilities: Option<crate::Capabilities>,
+    /// `None` until the server answered `initialize`
+    server: std::sync::Mutex<Option<crate::capabilities::ServerCapabilities>>,
+}
+
+/// State shared by the client, its pending requests and the reader thread.
+struct Connection {
+    req_to_ra: std::sync::Mutex<crate::transport::MessageWriter>,
+    router: std::sync::Mutex<crate::router::Router>,
+    /// `None` when the caller spawned the server and passed its pipes to [`Client::new`]
+    process: Option<std::sync::Mutex<crate::process::ServerProcess>>,
+    /// Shuts down a socket, called when the client is dropped.
+    close_transport: Option<Box<dyn Fn() + Send + Sync>>,
+    /// set by [`Client::set_transcript`]
+    transcript: std::sync::Mutex<Option<crate::transcript::Recorder>>,
+    /// the documents opened with `textDocument/didOpen` and their unsaved text
+    documents: std::sync::Mutex<crate::document::OpenDocuments>,
+    /// `Some` for [`Client::spawn_supervised`]. Held while sending, so nothing
+    /// reaches a restarted server before the replayed `initialize`.
+    supervisor: Option<std::sync::Mutex<crate::supervisor::Supervisor>>,
+    /// set by [`Client::exit`] and on drop, a server crashing after that is not restarted
+    closing: std::sync::atomic::AtomicBool,
+}
+
+impl Connection {
+    fn write(&self, msg: lsp_server::Message) -> crate::Result<()> {
+        self.write_message(msg).map_err(|err| self.write_error(err))
+    }
+
+    fn write_message(&self, msg: lsp_server::Message) -> std::io::Result<()> {
+        // recorded under the writer lock, so the transcript is in wire order
+        let mut req_to_ra = self.req_to_ra.lock().unwrap();
+        crate::trace::message(crate::Direction::ToServer, &msg);
+        self.record(crate::Direction::ToServer, &msg);
+        req_to_ra.write(msg)
+    }
+
+    fn write_error(&self, err: std::io::Error) -> crate::ClientError {
+        // the server is gone, tell why rather than failing with EPIPE
+        if err.kind() == std::io::ErrorKind::BrokenPipe {
+            crate::process::exit_error(&self.exit_error())
+        } else {
+            err.into()
+        }
+    }
+
+    fn read(
+        &self,
+        rsp_from_ra: &mut crate::transport::MessageReader,
+    ) -> std::io::Result<Option<lsp_server::Message>> {
+        let msg = rsp_from_ra.read()?;
+        if let Some(msg) = &msg {
+            crate::trace::message(crate::Direction::FromServer, msg);
+            self.record(crate::Direction::FromServer, msg);
+        }
+        Ok(msg)
+    }
+
+    fn record(&self, direction: crate::Direction, msg: &lsp_server::Message) {
+        crate::transcript::record(&self.transcript, direction, msg);
+    }
+
+    fn is_closing(&self) -> bool {
+        self.closing.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    fn kill(&self) {
+        if let Some(process) = &self.process {
+            // the lock is poisoned when a panic interrupted `exit`, kill anyway
+            let mut process = process.lock().unwrap_or_else(|err| err.into_inner());
+            process.kill();
+        }
+    }
+
+    /// The exit status and last stderr lines of a spawned server that exited unexpectedly.
+    fn exit_error(&self) -> Option<crate::process::ExitReport> {
+        let process = self.process.as_ref()?;
+        process.lock().unwrap().unexpected_exit()
+    }
+
+    /// Sends a request from the client, registered by `register` first.
+    fn send(
+        &self,
+        req: lsp_server::Request,
+        register: impl FnOnce(&mut crate::router::Router, bool) -> crate::Result<()>,
+    ) -> crate::Result<()> {
+        let supervisor = self
+            .supervisor
+            .as_ref()
+            .map(|supervisor| supervisor.lock().unwrap());
+        let retry = supervisor
+            .as_ref()
+            .is_some_and(|supervisor| supervisor.policy.retry_in_flight);
+        register(&mut self.router.lock().unwrap(), retry)?;
+        self.write_sent(req.into(), supervisor.as_deref())
+    }
+
+    /// Sends the notification `build` makes from the open documents, which then
+    /// follow it. Built, tracked and written under the documents lock, so
+    /// concurrent changes get consecutive versions and reach the server in order.
+    fn notify(
+        &self,
+        build: impl FnOnce(&crate::document::OpenDocuments) -> crate::Result<lsp_server::Notification>,
+    ) -> crate::Result<()> {
+        let supervisor = self
+            .supervisor
+            .as_ref()
+            .map(|supervisor| supervisor.lock().unwrap());
+        let mut documents = self.documents.lock().unwrap();
+        let notif = build(&documents)?;
+        documents.track(&notif);
+        self.write_sent(notif.into(), supervisor.as_deref())
+    }
+
+    /// Like [`Connection::notify`], for a notification the server does not want:
+    /// only the open documents follow it.
+    fn track(
+        &self,
+        build: impl FnOnce(&crate::document::OpenDocuments) -> crate::Result<lsp_server::Notification>,
+    ) -> crate::Result<()> {
+        let mut documents = self.documents.lock().unwrap();
+        let notif = build(&documents)?;
+        documents.track(&notif);
+        Ok(())
+    }
+
+    fn write_sent(
+        &self,
+        msg: lsp_server::Message,
+        supervisor: Option<&crate::supervisor::Supervisor>,
+    ) -> crate::Result<()> {
+        let restarting = supervisor.is_some_and(|supervisor| {
+            supervisor.restarts < supervisor.policy.max_restarts && !self.is_closing()
+        });
+        match self.write_message(msg) {
+            // crashed and not noticed by the reader thread yet: the restart sends the
+            // request again or fails it, and re-opens the documents
+            Err(err) if restarting && err.kind() == std::io::ErrorKind::BrokenPipe => Ok(()),
+            written => written.map_err(|err| self.write_error(err)),
+        }
+    }
+
+    /// Respawns a crashed supervised server, replays `initialize`/`initialized`,
+    /// re-opens the documents and sends the retried requests again. Returns the
+    /// new server's stdout, or `None` when the server is not supervised, already
+    /// used up its restarts or the client is closing.
+    fn restart(
+        self: &std::sync::Arc<Self>,
+        died: impl Fn() -> crate::ClientError,
+    ) -> crate::Result<Option<crate::transport::MessageReader>> {
+        let mut supervisor = match &self.supervisor {
+            Some(supervisor) => supervisor.lock().unwrap(),
+            None => return Ok(None),
+        };
+        if supervisor.restarts >= supervisor.policy.max_restarts || self.is_closing() {
+            return Ok(None);
+        }
+        supervisor.restarts += 1;
+        let (process, stdin, stdout) =
+            crate::process::ServerProcess::spawn(&mut (supervisor.command)(), &supervisor.stderr)?;
+        *self.req_to_ra.lock().unwrap() = crate::transport::MessageWriter::new(stdin);
+        if let Some(old) = &self.process {
+            *old.lock().unwrap() = process;
+        }
+        // dropped or exited while spawning: the new server is not wanted either
+        if self.is_closing() {
+            self.kill();
+            return Ok(None);
+        }
+        // a server that never answers `initialize` would block every request, kill it
+        let (handshake_done, handshake) = std::sync::mpsc::channel::<()>();
+        let handshake_timeout = supervisor.policy.handshake_timeout;
+        let conn = self.clone();
+        std::thread::Builder::new()
+            .name("lsp_client restart".to_string())
+            .spawn(move || {
+                if let Err(std::sync::mpsc::RecvTimeoutError::Timeout) =
+                    handshake.recv_timeout(handshake_timeout)
+                {
+                    tracing::warn!(
+                        "the restarted server did not initialize within {handshake_timeout:?}"
+                    );
+                    conn.kill();
+                }
+            })?;
+        let resend = self
+            .router
+            .lock()
+            .unwrap()
+            .restart(supervisor.policy.retry_in_flight, died);
+        let mut rsp_from_ra = crate::transport::MessageReader::new(stdout);
+        if let Some(params) = supervisor.initialize_params.clone() {
+            let id =
+                lsp_server::RequestId::from(format!("lsp_client/restart/{}", supervisor.restarts));
+            self.write(
+                lsp_server::Request {
+                    id: id.clone(),
+                    method: <lsp_types::request::Initialize as Request>::METHOD.to_string(),
+                    params,
+                }
+                .into(),
+            )?;
+            // the server may ask for configuration or progress tokens before it answers
+            loop {
+                match self.read(&mut rsp_from_ra)? {
+                    None => return Err(crate::ClientError::ServerExited),
+                    Some(lsp_server::Message::Response(rsp)) if rsp.id == id => {
+                        crate::protocol::result_value(rsp)?;
+                        drop(handshake_done);
+                        break;
+                    }
+                    Some(msg) => {
+                        let reply = self.router.lock().unwrap().route(msg);
+                        if let Some(reply) = reply {
+                            self.write(reply)?;
+                        }
+                    }
+                }
+            }
+            self.write(
+                crate::protocol::notification::<lsp_types::notification::Initialized>(
+                    lsp_types::InitializedParams {},
+                )?
+                .into(),
+            )?;
+            for did_open in self.documents.lock().unwrap().reopen() {
+                self.write(did_open?.into())?;
+            }
+        }
+        for req in resend {
+            self.write(req.into())?;
+        }
+        Ok(Some(rsp_from_ra))
+    }
+}
+
+/// Handle to a request sent with [`Client::send`] whose response has not been awaited yet.
+pub struct PendingRequest {
+    id: lsp_server::RequestId,
+    rsp_rx: std::sync::mpsc::Receiver<crate::Result<lsp_server::Response>>,
+    conn: std::sync::Arc<Connection>,
+    timeout: Option<std::time::Duration>,
+}
+
+impl PendingRequest {
+    pub fn id(&self) -> &lsp_server::RequestId {
+        &self.id
+    }
+
+    /// Blocks until the response arrives, returning the result payload.
+    /// Gives up after the client's default timeout, if one is set.
+    pub fn wait(self) -> crate::Result<Option<serde_json::Value>> {
+        crate::protocol::result_value(self.wait_resp()?)
+    }
+
+    /// Like [`PendingRequest::wait`] but gives up after `timeout`, cancelling the
+    /// request on the server and returning [`ClientError::Timeout`](crate::ClientError::Timeout).
+    pub fn wait_timeout(
+        mut self,
+        timeout: std::time::Duration,
+    ) -> crate::Result<Option<serde_json::Value>> {
+        self.timeout = Some(timeout);
+        self.wait()
+    }
+
+    /// Abandons the request and tells the server with `$/cancelRequest`.
+    pub fn cancel(self) -> crate::Result<()> {
+        self.abandon(crate::ClientError::Cancelled)
+    }
+
+    fn abandon(self, reason: crate::ClientError) -> crate::Result<()> {
+        let cancel = self.conn.router.lock().unwrap().cancel(&self.id, reason)?;
+        match cancel {
+            Some(cancel) => self.conn.write(cancel.into()),
+            None => Ok(()),
+        }
+    }
+
+    fn wait_resp(self) -> crate::Result<lsp_server::Response> {
+        let timeout = match self.timeout {
+            // the sender is only dropped without a value when the reader thread is gone
+            None => {
+                return self
+                    .rsp_rx
+                    .recv()
+                    .unwrap_or(Err(crate::ClientError::ServerExited))
+            }
+            Some(timeout) => timeout,
+        };
+        match self.rsp_rx.recv_timeout(timeout) {
+            Ok(rsp) => rsp,
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                self.abandon(crate::ClientError::Timeout)?;
+                Err(crate::ClientError::Timeout)
+            }
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                Err(crate::ClientError::ServerExited)
+            }
+        }
+    }
+}
+
+/// [`PendingRequest`] for request `R`, decoding the response into `R::Result`.
+pub struct TypedPendingRequest<R: Request> {
+    pending: PendingRequest,
+    _request: std::marker::PhantomData<fn() -> R>,
+}
+
+impl<R: Request> TypedPendingRequest<R> {
+    pub fn id(&self) -> &lsp_server::RequestId {
+        self.pending.id()
+    }
+
+    /// Blocks until the response arrives and deserializes its result.
+    pub fn wait(self) -> crate::Result<R::Result> {
+        crate::protocol::result::<R>(self.pending.wait_resp()?)
+    }
+
+    /// Like [`TypedPendingRequest::wait`] but cancels the request after `timeout`.
+    pub fn wait_timeout(mut self, timeout: std::time::Duration) -> crate::Result<R::Result> {
+        self.pending.timeout = Some(timeout);
+        self.wait()
+    }
+
+    /// Abandons the request and tells the server with `$/cancelRequest`.
+    pub fn cancel(self) -> crate::Result<()> {
+        self.pending.cancel()
+    }
+}
+
+impl Client {
+    /// Creates a client from the piped stdin/stdout of a spawned language server
+    /// and starts the thread reading the server's messages.
+    pub fn new(
+        stdin: std::process::ChildStdin,
+        stdout: std::process::ChildStdout,
+    ) -> crate::Result<Self> {
+        Self::from_streams(stdin, stdout)
+    }
+
+    /// Talks to a server over any pair of byte streams, e.g. the two ends of a
+    /// pipe or a TLS connection, with the same framing as on stdio.
+    pub fn from_streams(
+        to_server: impl std::io::Write + Send + 'static,
+        from_server: impl std::io::Read + Send + 'static,
+    ) -> crate::Result<Self> {
+        Self::with_transport(
+            crate::transport::MessageWriter::new(to_server),
+            crate::transport::MessageReader::new(from_server),
+            None,
+            None,
+            None,
+        )
+    }
+
+    /// Connects to a server already listening on a TCP socket, e.g. one started
+    /// by a daemon or in a container. The socket is shut down when the client is dropped.
+    pub fn connect_tcp(addr: impl std::net::ToSocketAddrs) -> crate::Result<Self> {
+        let (writer, reader, close) = crate::transport::tcp(addr)?;
+        Self::with_transport(writer, reader, None, None, Some(close))
+    }
+
+    /// Connects to a server already listening on the Unix domain socket at `path`.
+    #[cfg(unix)]
+    pub fn connect_unix(path: impl AsRef<std::path::Path>) -> crate::Result<Self> {
+        let (writer, reader, close) = crate::transport::unix(path.as_ref())?;
+        Self::with_transport(writer, reader, None, None, Some(close))
+    }
+
+    /// Talks to a server running in this process over the channels of
+    /// `lsp_server::Connection::memory()`, without any framing. `conn` is the
+    /// client's half, the server's half goes to e.g. a `main_loop` thread.
+    pub fn from_connection(conn: lsp_server::Connection) -> crate::Result<Self> {
+        let (writer, reader) = crate::transport::memory(conn);
+        Self::with_transport(writer, reader, None, None, None)
+    }
+
+    /// Runs `serve` on a new thread with the server's half of
+    /// `lsp_server::Connection::memory()` and talks to it, see [`Client::from_connection`].
+    /// The thread should return once the receiver disconnects or `exit` arrives.
+    pub fn in_process<F>(serve: F) -> crate::Result<Self>
+    where
+        F: FnOnce(lsp_server::Connection) + Send + 'static,
+    {
+        let (client, server) = lsp_server::Connection::memory();
+        std::thread::Builder::new()
+            .name("lsp_client in-process server".to_string())
+            .spawn(move || serve(server))?;
+        Self::from_connection(client)
+    }
+
+    /// Spawns the language server, e.g. `Command::new("rust-analyzer")`, with its
+    /// stdio piped. Its stderr is forwarded to `tracing`, and the last lines are kept
+    /// to report in [`ClientError::ServerDied`](crate::ClientError::ServerDied).
+    pub fn spawn(command: std::process::Command) -> crate::Result<Self> {
+        Self::spawn_with_stderr(command, crate::StderrPolicy::default())
+    }
+
+    /// Spawns the server `profile` describes and talks to it accordingly.
+    pub fn spawn_profile(profile: impl crate::ServerProfile + 'static) -> crate::Result<Self> {
+        let mut client = Self::spawn_with_stderr(profile.command()?, profile.stderr())?;
+        client.set_profile(profile);
+        Ok(client)
+    }
+
+    /// Like [`Client::spawn`], with the server's stderr going where `stderr` says.
+    pub fn spawn_with_stderr(
+        mut command: std::process::Command,
+        stderr: crate::StderrPolicy,
+    ) -> crate::Result<Self> {
+        let (process, stdin, stdout) = crate::process::ServerProcess::spawn(&mut command, &stderr)?;
+        Self::with_transport(
+            crate::transport::MessageWriter::new(stdin),
+            crate::transport::MessageReader::new(stdout),
+            Some(process),
+            None,
+            None,
+        )
+    }
+
+    /// Like [`Client::spawn`], but when the server crashes it is spawned again
+    /// from a new `make_command()`, given the last `initialize` and the open
+    /// documents, according to `policy`. Requests sent while the server is down
+    /// are retried or failed like those in flight at crash time, notifications
+    /// other than the document ones replayed on restart are lost. Every server
+    /// started has its stderr going where `stderr` says.
+    pub fn spawn_supervised<F>(
+        mut make_command: F,
+        policy: crate::RestartPolicy,
+        stderr: crate::StderrPolicy,
+    ) -> crate::Result<Self>
+    where
+        F: FnMut() -> std::process::Command + Send + 'static,
+    {
+        let (process, stdin, stdout) =
+            crate::process::ServerProcess::spawn(&mut make_command(), &stderr)?;
+        let supervisor = crate::supervisor::Supervisor {
+            command: Box::new(make_command),
+            stderr,
+            policy,
+            restarts: 0,
+            initialize_params: None,
+        };
+        Self::with_transport(
+            crate::transport::MessageWriter::new(stdin),
+            crate::transport::MessageReader::new(stdout),
+            Some(process),
+            Some(supervisor),
+            None,
+        )
+    }
+
+    fn with_transport(
+        req_to_ra: crate::transport::MessageWriter,
+        rsp_from_ra: crate::transport::MessageReader,
+        process: Option<crate::process::ServerProcess>,
+        supervisor: Option<crate::supervisor::Supervisor>,
+        close_transport: Option<Box<dyn Fn() + Send + Sync>>,
+    ) -> crate::Result<Self> {
+        let conn = std::sync::Arc::new(Connection {
+            req_to_ra: std::sync::Mutex::new(req_to_ra),
+            router: std::sync::Mutex::default(),
+            process: process.map(std::sync::Mutex::new),
+            close_transport,
+            transcript: std::sync::Mutex::new(None),
+            documents: std::sync::Mutex::default(),
+            supervisor: supervisor.map(std::sync::Mutex::new),
+            closing: std::sync::atomic::AtomicBool::new(false),
+        });
+        let reader_conn = conn.clone();
+        std::thread::Builder::new()
+            .name("lsp_client reader".to_string())
+            .spawn(move || read_loop(rsp_from_ra, reader_conn))?;
+        Ok(Self {
+            conn,
+            req_id: crate::protocol::ReqId::new(),
+            default_timeout: None,
+            profile: std::sync::Arc::new(crate::RustAnalyzerProfile::default()),
+            shutdown_grace: crate::process::DEFAULT_SHUTDOWN_GRACE,
+            capabilities: None,
+            server: std::sync::Mutex::new(None),
+        })
+    }
+
+    /// Sets the timeout applied to every request that is not given one explicitly.
+    /// `None`, the default, waits forever.
+    pub fn set_default_timeout(&mut self, timeout: Option<std::time::Duration>) {
+        self.default_timeout = timeout;
+    }
+
+    /// Sets the kind of server talked to, [`RustAnalyzerProfile`](crate::RustAnalyzerProfile)
+    /// by default. Its command is only used by [`Client::spawn_profile`].
+    pub fn set_profile(&mut self, profile: impl crate::ServerProfile + 'static) {
+        self.profile = std::sync::Arc::new(profile);
+    }
+
+    /// Replaces the rust-analyzer settings used by the next `initialize`,
+    /// by default only `checkOnSave` is disabled. Shorthand for a
+    /// [`RustAnalyzerProfile`](crate::RustAnalyzerProfile) with `ra_config`.
+    pub fn set_ra_config(&mut self, ra_config: crate::RaConfig) {
+        self.set_profile(crate::RustAnalyzerProfile::default().ra_config(ra_config));
+    }
+
+    /// Sets how long [`Client::exit`] waits for a spawned server to exit, and
+    /// again after SIGTERM, before killing it. 5 seconds by default.
+    pub fn set_shutdown_grace(&mut self, grace: std::time::Duration) {
+        self.shutdown_grace = grace;
+    }
+
+    /// Records every message sent and received from now on to `transcript`, e.g.
+    /// a `File`, as JSON lines of [`TranscriptEntry`](crate::TranscriptEntry).
+    /// [`MockServer::replay`](crate::mock::MockServer::replay) plays it back.
+    pub fn set_transcript(&mut self, transcript: impl std::io::Write + Send + 'static) {
+        *self.conn.transcript.lock().unwrap() = Some(crate::transcript::Recorder::new(transcript));
+    }
+
+    /// The last stderr lines of a spawned server, kept unless its
+    /// [`StderrPolicy`](crate::StderrPolicy) sends them elsewhere.
+    pub fn stderr_tail(&self) -> Vec<String> {
+        self.conn
+            .process
+            .as_ref()
+            .map_or_else(Vec::new, |process| process.lock().unwrap().stderr_tail())
+    }
+
+    /// How many times a supervised server was restarted after a crash.
+    pub fn restarts(&self) -> u32 {
+        self.conn
+            .supervisor
+            .as_ref()
+            .map_or(0, |supervisor| supervisor.lock().unwrap().restarts)
+    }
+
+    /// Replaces the client capabilities the profile declares in the next `initialize`.
+    /// Readiness tracking needs `window.workDoneProgress` or rust-analyzer's
+    /// `serverStatusNotification`, both declared by [`Capabilities::default`](crate::Capabilities::default).
+    pub fn set_capabilities(&mut self, capabilities: crate::Capabilities) {
+        self.capabilities = Some(capabilities);
+    }
+
+    /// The capabilities the server announced in `initialize`, `None` before it answered.
+    pub fn server_capabilities(&self) -> Option<lsp_types::ServerCapabilities> {
+        let server = self.server.lock().unwrap();
+        server
+            .as_ref()
+            .map(|server| server.result.capabilities.clone())
+    }
+
+    /// The server's name and version from its `initialize` response.
+    pub fn server_info(&self) -> Option<lsp_types::ServerInfo> {
+        let server = self.server.lock().unwrap();
+        server
+            .as_ref()
+            .and_then(|server| server.result.server_info.clone())
+    }
+
+    /// The position encoding the server chose, UTF-16 unless it picked another one.
+    pub fn position_encoding(&self) -> crate::PositionEncoding {
+        let server = self.server.lock().unwrap();
+        server
+            .as_ref()
+            .map(|server| server.position_encoding())
+            .unwrap_or(crate::PositionEncoding::Utf16)
+    }
+
+    /// Whether the server announced the capability request `method` needs.
+    /// Before `initialize`, and for methods outside the spec, this is always true.
+    pub fn supports(&self, method: &str) -> bool {
+        let server = self.server.lock().unwrap();
+        server.as_ref().is_none_or(|server| server.supports(method))
+    }
+
+    /// Allocates the id for the next request sent to the server.
+    pub fn next_id(&self) -> lsp_server::RequestId {
+        self.req_id.inc()
+    }
+
+    /// Performs the `initialize`/`initialized` handshake and waits until
+    /// the server finished loading the workspace, failing with
+    /// [`ClientError::Timeout`](crate::ClientError::Timeout) if it takes longer than 5 minutes.
+    pub fn init(&self, workspace: &crate::Workspace) -> crate::Result<()> {
+        self.initialize(workspace)?;
+        let start = std::time::Instant::now();
+        self.wait_ready(crate::protocol::DEFAULT_READY_DEADLINE, |_| {})?;
+        tracing::info!(
+            "{} loading workspace total wait is {:?}",
+            self.profile.name(),
+            start.elapsed()
+        );
+        Ok(())
+    }
+
+    /// Performs the `initialize`/`initialized` handshake only.
+    pub fn initialize(&self, workspace: &crate::Workspace) -> crate::Result<()> {
+        let init_req = crate::protocol::initialize(
+            self.req_id.inc(),
+            workspace,
+            &self.profile,
+            self.capabilities.as_ref(),
+            &mut self.conn.router.lock().unwrap(),
+        )?;
+        if let Some(supervisor) = &self.conn.supervisor {
+            supervisor.lock().unwrap().initialize_params = Some(init_req.params.clone());
+        }
+        let result = self.send_req(init_req)?.unwrap_or_default();
+        let server = crate::capabilities::ServerCapabilities::parse(result)?;
+        let encoding = server.position_encoding();
+        *self.server.lock().unwrap() = Some(server);
+        self.conn.documents.lock().unwrap().set_encoding(encoding);
+        self.notify::<lsp_types::notification::Initialized>(lsp_types::InitializedParams {})
+    }
+
+    /// Blocks until the server finished loading the workspace, as told by the
+    /// profile's [`ReadinessStrategy`](crate::ReadinessStrategy): for rust-analyzer
+    /// `experimental/serverStatus` or the end of its loading `$/progress`
+    /// ("Fetching", "Indexing", "Building", "Roots Scanned", ...).
+    /// `on_progress` sees every progress update meanwhile.
+    pub fn wait_ready<F>(
+        &self,
+        deadline: std::time::Duration,
+        mut on_progress: F,
+    ) -> crate::Result<()>
+    where
+        F: FnMut(&crate::Progress),
+    {
+        let start = std::time::Instant::now();
+        let (event_tx, event_rx) = std::sync::mpsc::channel();
+        let ready = self
+            .conn
+            .router
+            .lock()
+            .unwrap()
+            .watch_progress(Box::new(move |event| event_tx.send(event.clone()).is_ok()))?;
+        if ready {
+            return Ok(());
+        }
+        loop {
+            let remaining = deadline
+                .checked_sub(start.elapsed())
+                .ok_or(crate::ClientError::Timeout)?;
+            match event_rx.recv_timeout(remaining) {
+                Ok(event) => {
+                    if event.ready(&mut on_progress) {
+                        return Ok(());
+                    }
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {
+                    return Err(crate::ClientError::Timeout)
+                }
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => {
+                    return Err(crate::ClientError::ServerExited)
+                }
+            }
+        }
+    }
+
+    /// Sends request `R`, e.g. `lsp_types::request::References` or
+    /// `lsp_client::ra_ext::WorkspaceSymbol`, and blocks for its typed result.
+    pub fn request<R: Request>(&self, params: R::Params) -> crate::Result<R::Result> {
+        self.send_request::<R>(params)?.wait()
+    }
+
+    /// Like [`Client::request`] but cancels the request after `timeout`.
+    pub fn request_timeout<R: Request>(
+        &self,
+        params: R::Params,
+        timeout: std::time::Duration,
+    ) -> crate::Result<R::Result> {
+        self.send_request::<R>(params)?.wait_timeout(timeout)
+    }
+
+    /// Sends request `R` without waiting for its response.
+    pub fn send_request<R: Request>(
+        &self,
+        params: R::Params,
+    ) -> crate::Result<TypedPendingRequest<R>> {
+        let req = crate::protocol::request::<R>(self.req_id.inc(), params)?;
+        Ok(TypedPendingRequest {
+            pending: self.send(req)?,
+            _request: std::marker::PhantomData,
+        })
+    }
+
+    /// Sends notification `N`.
+    pub fn notify<N: Notification>(&self, params: N::Params) -> crate::Result<()> {
+        let notif = crate::protocol::notification::<N>(params)?;
+        self.conn.notify(|_| Ok(notif))
+    }
+
+    /// Opens `uri` on the server with `text` as its content, at version 1. Until
+    /// [`Client::close_document`] the server analyzes this in-memory overlay
+    /// rather than the file on disk, which need not even exist. Fails with
+    /// [`ClientError::DocumentAlreadyOpen`](crate::ClientError::DocumentAlreadyOpen)
+    /// when `uri` is open already, see [`Client::change_document`].
+    pub fn open_document(
+        &self,
+        uri: lsp_types::Url,
+        language_id: &str,
+        text: String,
+    ) -> crate::Result<()> {
+        self.conn.notify(|documents| {
+            if documents.get(&uri).is_some() {
+                return Err(crate::ClientError::DocumentAlreadyOpen(uri));
+            }
+            crate::protocol::notification::<lsp_types::notification::DidOpenTextDocument>(
+                lsp_types::DidOpenTextDocumentParams {
+                    text_document: lsp_types::TextDocumentItem::new(
+                        uri,
+                        language_id.to_string(),
+                        1,
+                        text,
+                    ),
+                },
+            )
+        })
+    }
+
+    /// Applies `changes`, in order, to the open document `uri` and sends them
+    /// with the next version, which is returned. A change without a range
+    /// replaces the whole text. A server taking full syncs only is sent the
+    /// resulting text instead, one taking no changes is sent nothing.
+    pub fn change_document(
+        &self,
+        uri: &lsp_types::Url,
+        changes: Vec<lsp_types::TextDocumentContentChangeEvent>,
+    ) -> crate::Result<i32> {
+        let sync_kind = self
+            .server
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|server| server.sync_kind());
+        let full_sync = sync_kind == Some(lsp_types::TextDocumentSyncKind::FULL);
+        let mut version = 0;
+        let change = |documents: &crate::document::OpenDocuments| {
+            let document = documents
+                .get(uri)
+                .ok_or_else(|| crate::ClientError::DocumentNotOpen(uri.clone()))?;
+            let content_changes = if full_sync {
+                let mut text = document.text.clone();
+                for change in changes {
+                    crate::document::apply_change(&mut text, change, documents.encoding());
+                }
+                vec![lsp_types::TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text,
+                }]
+            } else {
+                changes
+            };
+            version = document.version + 1;
+            crate::protocol::notification::<lsp_types::notification::DidChangeTextDocument>(
+                lsp_types::DidChangeTextDocumentParams {
+                    text_document: lsp_types::VersionedTextDocumentIdentifier::new(
+                        uri.clone(),
+                        version,
+                    ),
+                    content_changes,
+                },
+            )
+        };
+        if sync_kind == Some(lsp_types::TextDocumentSyncKind::NONE) {
+            self.conn.track(change)?;
+        } else {
+            self.conn.notify(change)?;
+        }
+        Ok(version)
+    }
+
+    /// Tells the server the open document `uri` was saved, with its text when
+    /// the server asked for it. Nothing is written to disk.
+    pub fn save_document(&self, uri: &lsp_types::Url) -> crate::Result<()> {
+        let include_text = self
+            .server
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|server| server.save_includes_text());
+        self.conn.notify(|documents| {
+            let document = documents
+                .get(uri)
+                .ok_or_else(|| crate::ClientError::DocumentNotOpen(uri.clone()))?;
+            crate::protocol::notification::<lsp_types::notification::DidSaveTextDocument>(
+                lsp_types::DidSaveTextDocumentParams {
+                    text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+                    text: include_text.then(|| document.text.clone()),
+                },
+            )
+        })
+    }
+
+    /// Closes the open document `uri`, the server goes back to the file on disk.
+    pub fn close_document(&self, uri: &lsp_types::Url) -> crate::Result<()> {
+        self.conn.notify(|documents| {
+            if documents.get(uri).is_none() {
+                return Err(crate::ClientError::DocumentNotOpen(uri.clone()));
+            }
+            crate::protocol::notification::<lsp_types::notification::DidCloseTextDocument>(
+                lsp_types::DidCloseTextDocumentParams {
+                    text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+                },
+            )
+        })
+    }
+
+    /// The current text of the open document `uri`, with every change applied.
+    pub fn document_text(&self, uri: &lsp_types::Url) -> crate::Result<String> {
+        let documents = self.conn.documents.lock().unwrap();
+        documents
+            .get(uri)
+            .map(|document| document.text.clone())
+            .ok_or_else(|| crate::ClientError::DocumentNotOpen(uri.clone()))
+    }
+
+    /// The [`LineIndex`](crate::LineIndex) of the current text of the open
+    /// document `uri`, converting its positions in [`Client::position_encoding`].
+    pub fn line_index(
+        &self,
+        uri: &lsp_types::Url,
+    ) -> crate::Result<std::sync::Arc<crate::LineIndex>> {
+        let mut documents = self.conn.documents.lock().unwrap();
+        documents
+            .line_index(uri)
+            .ok_or_else(|| crate::ClientError::DocumentNotOpen(uri.clone()))
+    }
+
+    /// The position of the identifier of symbol `name` declared at `location`,
+    /// e.g. a `workspace/symbol` result spanning the whole item with its
+    /// attributes, doc comments and modifiers: the position to ask
+    /// `textDocument/references` about. Taken from the `selectionRange` of the
+    /// nested `textDocument/documentSymbol` answer when the server gives one,
+    /// else found scanning the source, the open overlay or the file on disk,
+    /// falling back to the start of `location`. See [`Client::name_positions`]
+    /// for many symbols.
+    pub fn name_position(
+        &self,
+        name: &str,
+        location: &lsp_types::Location,
+    ) -> crate::Result<lsp_types::Position> {
+        let positions = self.name_positions([(name, location)])?;
+        Ok(positions[0])
+    }
+
+    /// [`Client::name_position`] of every `(name, location)` in `symbols`, in
+    /// order. Every file is asked for its symbols once, all files at once, and
+    /// read once when scanned. A file the server answers `documentSymbol` with
+    /// an error for, e.g. `ContentModified`, is scanned, one that cannot be
+    /// read gives the start of `location`.
+    pub fn name_positions<'a>(
+        &self,
+        symbols: impl IntoIterator<Item = (&'a str, &'a lsp_types::Location)>,
+    ) -> crate::Result<Vec<lsp_types::Position>> {
+        let symbols: Vec<_> = symbols.into_iter().collect();
+        let mut uris = Vec::new();
+        for (_, location) in &symbols {
+            if !uris.contains(&&location.uri) {
+                uris.push(&location.uri);
+            }
+        }
+        let mut document_symbols = std::collections::HashMap::new();
+        if self.supports(<lsp_types::request::DocumentSymbolRequest as Request>::METHOD) {
+            let pending = uris
+                .iter()
+                .map(|&uri| {
+                    self.send_request::<lsp_types::request::DocumentSymbolRequest>(
+                        lsp_types::DocumentSymbolParams {
+                            text_document: lsp_types::TextDocumentIdentifier::new(uri.clone()),
+                            work_done_progress_params: Default::default(),
+                            partial_result_params: Default::default(),
+                        },
+                    )
+                })
+                .collect::<crate::Result<Vec<_>>>()?;
+            for (uri, pending) in uris.iter().zip(pending) {
+                match pending.wait() {
+                    Ok(Some(lsp_types::DocumentSymbolResponse::Nested(symbols))) => {
+                        document_symbols.insert(*uri, symbols);
+                    }
+                    // flat symbols have no selection range
+                    Ok(_) => {}
+                    Err(
+                        crate::ClientError::Rpc { .. }
+                        | crate::ClientError::ContentModified
+                        | crate::ClientError::Cancelled,
+                    ) => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        let encoding = self.position_encoding();
+        let mut sources = std::collections::HashMap::new();
+        let mut positions = Vec::with_capacity(symbols.len());
+        for (name, location) in symbols {
+            let selection = document_symbols
+                .get(&location.uri)
+                .and_then(|symbols| crate::symbol::selection_range(symbols, name, location.range));
+            if let Some(range) = selection {
+                positions.push(range.start);
+                continue;
+            }
+            // a file that cannot be read is only tried once
+            let source = match sources.entry(&location.uri) {
+                std::collections::hash_map::Entry::Occupied(source) => source.into_mut(),
+                std::collections::hash_map::Entry::Vacant(source) => {
+                    let text = match self.source_text(&location.uri) {
+                        Ok(text) => Some(text),
+                        Err(err) => {
+                            tracing::debug!("cannot scan {} for symbols: {err}", location.uri);
+                            None
+                        }
+                    };
+                    source.insert(text.map(|text| {
+                        let line_index = crate::LineIndex::new(&text);
+                        (text, line_index)
+                    }))
+                }
+            };
+            let (text, line_index) = match source {
+                Some(source) => source,
+                None => {
+                    positions.push(location.range.start);
+                    continue;
+                }
+            };
+            let start = line_index.offset_at(location.range.start, encoding);
+            let end = line_index
+                .offset_at(location.range.end, encoding)
+                .max(start);
+            positions.push(match crate::symbol::scan_name(&text[start..end], name) {
+                Some(offset) => line_index.position_at(start + offset, encoding),
+                None => location.range.start,
+            });
+        }
+        Ok(positions)
+    }
+
+    /// The open overlay of `uri`, else the file on disk.
+    fn source_text(&self, uri: &lsp_types::Url) -> crate::Result<String> {
+        match self.document_text(uri) {
+            Ok(text) => Ok(text),
+            Err(_) => {
+                let path = uri
+                    .to_file_path()
+                    .map_err(|_| crate::ClientError::InvalidPath(uri.path().into()))?;
+                Ok(std::fs::read_to_string(path)?)
+            }
+        }
+    }
+
+    /// The version of the open document `uri`: 1 when opened, incremented by
+    /// every [`Client::change_document`].
+    pub fn document_version(&self, uri: &lsp_types::Url) -> crate::Result<i32> {
+        let documents = self.conn.documents.lock().unwrap();
+        documents
+            .get(uri)
+            .map(|document| document.version)
+            .ok_or_else(|| crate::ClientError::DocumentNotOpen(uri.clone()))
+    }
+
+    /// Sends `req` and blocks until its response arrives, returning the result payload.
+    /// A JSON-RPC error response is returned as [`ClientError::Rpc`](crate::ClientError::Rpc).
+    pub fn send_req(&self, req: lsp_server::Request) -> crate::Result<Option<serde_json::Value>> {
+        self.send(req)?.wait()
+    }
+
+    /// Sends `req` without waiting for its response, so many requests can be in flight at once.
+    /// Fails with [`ClientError::Unsupported`](crate::ClientError::Unsupported) without sending
+    /// when the server did not announce the capability `req` needs.
+    pub fn send(&self, req: lsp_server::Request) -> crate::Result<PendingRequest> {
+        if !self.supports(&req.method) {
+            return Err(crate::ClientError::Unsupported(req.method));
+        }
+        let id = req.id.clone();
+        let (rsp_tx, rsp_rx) = std::sync::mpsc::channel();
+        let retry = req.clone();
+        let on_response = crate::trace::on_response(&req, move |rsp| {
+            let _ = rsp_tx.send(rsp);
+        });
+        self.conn.send(req, |router, retry_in_flight| {
+            router.register(id.clone(), on_response, retry_in_flight.then_some(retry))
+        })?;
+        Ok(PendingRequest {
+            id,
+            rsp_rx,
+            conn: self.conn.clone(),
+            timeout: self.default_timeout,
+        })
+    }
+
+    /// Subscribes to server notifications of `method`, e.g. `textDocument/publishDiagnostics`.
+    pub fn subscribe(&self, method: &str) -> std::sync::mpsc::Receiver<lsp_server::Notification> {
+        self.subscribe_method(Some(method.to_string()))
+    }
+
+    /// Subscribes to every server notification.
+    pub fn subscribe_all(&self) -> std::sync::mpsc::Receiver<lsp_server::Notification> {
+        self.subscribe_method(None)
+    }
+
+    fn subscribe_method(
+        &self,
+        method: Option<String>,
+    ) -> std::sync::mpsc::Receiver<lsp_server::Notification> {
+        let (notif_tx, notif_rx) = std::sync::mpsc::channel();
+        self.conn.router.lock().unwrap().subscribe(
+            method,
+            Box::new(move |notif| notif_tx.send(notif.clone()).is_ok()),
+        );
+        notif_rx
+    }
+
+    /// Answers server-to-client requests of `method` with `handler` instead of the default reply.
+    /// It also replaces the `workspace/configuration` and `workspace/workspaceFolders`
+    /// answers taken from the profile and workspace, registered before `initialize` or after.
+    pub fn on_request<F>(&self, method: &str, handler: F)
+    where
+        F: FnMut(serde_json::Value) -> Result<serde_json::Value, lsp_server::ResponseError>
+            + Send
+            + 'static,
+    {
+        self.conn
+            .router
+            .lock()
+            .unwrap()
+            .on_request(method.to_string(), Box::new(handler));
+    }
+
+    /// Sends `shutdown` followed by the `exit` notification. A server with
+    /// [`Quirks::no_shutdown_response`](crate::Quirks::no_shutdown_response)
+    /// is only given a second to answer `shutdown`.
+    ///
+    /// A server started by [`Client::spawn`] is then given the shutdown grace
+    /// period to exit, sent SIGTERM, and killed after another grace period. Its
+    /// exit status is returned, or [`ClientError::ServerDied`](crate::ClientError::ServerDied)
+    /// if it did not exit successfully.
+    pub fn exit(&self) -> crate::Result<Option<std::process::ExitStatus>> {
+        self.conn
+            .closing
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        let process = match &self.conn.process {
+            Some(process) => process,
+            None => {
+                self.shutdown()?;
+                self.notify::<lsp_types::notification::Exit>(())?;
+                return Ok(None);
+            }
+        };
+        process.lock().unwrap().set_shutting_down();
+        let exit = self
+            .shutdown()
+            .and_then(|()| self.notify::<lsp_types::notification::Exit>(()));
+        process
+            .lock()
+            .unwrap()
+            .finish(self.shutdown_grace, exit)
+            .map(Some)
+    }
+
+    fn shutdown(&self) -> crate::Result<()> {
+        let timeout = crate::protocol::shutdown_timeout(&*self.profile, self.default_timeout);
+        let shutdown = self.send_request::<lsp_types::request::Shutdown>(())?;
+        let result = match timeout {
+            Some(timeout) => shutdown.wait_timeout(timeout),
+            None => shutdown.wait(),
+        };
+        crate::protocol::shutdown_result(&*self.profile, result)
+    }
+}
+
+impl Drop for Client {
+    /// Best-effort cleanup: a spawned server still running is killed, a socket is
+    /// shut down and an in-process server sees its receiver disconnect.
+    fn drop(&mut self) {
+        self.conn
+            .closing
+            .store(true, std::sync::atomic::Ordering::SeqCst);
+        if let Some(close) = &self.conn.close_transport {
+            close();
+        }
+        self.conn
+            .req_to_ra
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .close();
+        self.conn.kill();
+    }
+}
+
+// rust-analyzer interleaves `$/progress`, `window/logMessage` and requests such as
+// `window/workDoneProgress/create` with responses, so every message goes through the router
+fn read_loop(mut rsp_from_ra: crate::transport::MessageReader, conn: std::sync::Arc<Connection>) {
+    'server: loop {
+        let failed = loop {
+            let msg = match conn.read(&mut rsp_from_ra) {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break None,
+                Err(err) if crate::protocol::is_eof(&err) => break None,
+                Err(err) => break Some(err),
+            };
+            let reply = conn.router.lock().unwrap().route(msg);
+            if let Some(reply) = reply {
+                if conn.write(reply).is_err() {
+                    break None;
+                }
+            }
+        };
+        // the server may still be running, it is not restarted but can't be talked to any more
+        if let Some(err) = failed {
+            tracing::warn!("cannot read from the server: {err}");
+            conn.router
+                .lock()
+                .unwrap()
+                .close(crate::protocol::read_error(err));
+            break;
+        }
+        let unexpected_exit = conn.exit_error();
+        // only a crash is restarted, not an exit the client asked for
+        let crashed = unexpected_exit.is_some();
+        let exited = move || crate::process::exit_error(&unexpected_exit);
+        if crashed {
+            // a new server failing during the handshake counts as another crash
+            loop {
+                match conn.restart(&exited) {
+                    Ok(Some(restarted)) => {
+                        rsp_from_ra = restarted;
+                        continue 'server;
+                    }
+                    // not supervised or out of restarts
+                    Ok(None) => break,
+                    Err(err) => tracing::warn!("restarting the server failed: {err}"),
+                }
+            }
+        }
+        conn.router.lock().unwrap().close(exited);
+        break;
+    }
+}
+
+#[test]
+fn surface_read_errors() {
+    let read_error = |from_server: &'static [u8]| {
+        let client = Client::from_streams(std::io::sink(), from_server).unwrap();
+        let req = lsp_server::Request::new(client.next_id(), "mock/echo".to_string(), ());
+        client.send_req(req).unwrap_err()
+    };
+    let err = read_error(b"Content-Length: 6\r\n\r\n{oops}");
+    assert!(matches!(err, crate::ClientError::Json(_)), "{err:?}");
+    let err = read_error(b"Content-Length: six\r\n\r\n");
+    assert!(
+        matches!(&err, crate::ClientError::Io(err) if err.kind() == std::io::ErrorKind::InvalidData),
+        "{err:?}"
+    );
+    // cut off in the middle of a message, the server is gone
+    let err = read_error(b"Content-Length: 60\r\n\r\n{}");
+    assert!(matches!(err, crate::ClientError::ServerExited), "{err:?}");
+}
+
+#[test]
+fn time_out_and_cancel() {
+    let timeout = std::time::Duration::from_millis(50);
+    let (mut client, mock) = crate::mock::MockServer::new()
+        .hold_request("mock/slow")
+        .expect_notification("$/cancelRequest")
+        .hold_request("mock/default")
+        .expect_notification("$/cancelRequest")
+        // too late, dropped by the client
+        .reply(
+            "mock/slow",
+            crate::mock::Reply::Result(serde_json::json!(1)),
+        )
+        .reply(
+            "mock/default",
+            crate::mock::Reply::Result(serde_json::json!(2)),
+        )
+        .expect_request(
+            "mock/cancelled",
+            crate::mock::Reply::Error {
+                code: lsp_server::ErrorCode::RequestCanceled as i32,
+                message: "cancelled".to_string(),
+            },
+        )
+        .start()
+        .unwrap();
+    let req = |client: &Client, method: &str| {
+        lsp_server::Request::new(client.next_id(), method.to_string(), ())
+    };
+    let slow = client.send(req(&client, "mock/slow")).unwrap();
+    let slow_id = slow.id().clone();
+    assert!(matches!(
+        slow.wait_timeout(timeout),
+        Err(crate::ClientError::Timeout)
+    ));
+    client.set_default_timeout(Some(timeout));
+    let default = req(&client, "mock/default");
+    let default_id = default.id.clone();
+    assert!(matches!(
+        client.send_req(default),
+        Err(crate::ClientError::Timeout)
+    ));
+    assert!(matches!(
+        client.send_req(req(&client, "mock/cancelled")),
+        Err(crate::ClientError::Cancelled)
+    ));
+
+    let report = mock.finish();
+    report.assert_ok();
+    let cancelled: Vec<_> = report
+        .notifications("$/cancelRequest")
+        .iter()
+        .map(|notif| notif.params["id"].clone())
+        .collect();
+    assert_eq!(
+        cancelled,
+        [
+            serde_json::to_value(slow_id).unwrap(),
+            serde_json::to_value(default_id).unwrap()
+        ]
+    );
+}
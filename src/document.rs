@@ -0,0 +1,309 @@
+use lsp_types::notification::Notification;
+
+/// The documents the client has open on the server, followed through the
+/// `textDocument/did*` notifications it sends: the overlays the server analyzes
+/// instead of the files on disk, re-opened on a restarted server.
+pub(crate) struct OpenDocuments {
+    documents: std::collections::HashMap<lsp_types::Url, Document>,
+    /// the encoding of `didChange` ranges, negotiated in `initialize`
+    encoding: crate::PositionEncoding,
+}
+
+struct Document {
+    item: lsp_types::TextDocumentItem,
+    /// built on demand, dropped by every change
+    line_index: Option<std::sync::Arc<crate::LineIndex>>,
+}
+
+impl Default for OpenDocuments {
+    fn default() -> Self {
+        Self {
+            documents: std::collections::HashMap::new(),
+            encoding: crate::PositionEncoding::Utf16,
+        }
+    }
+}
+
+impl OpenDocuments {
+    pub(crate) fn set_encoding(&mut self, encoding: crate::PositionEncoding) {
+        self.encoding = encoding;
+    }
+
+    pub(crate) fn encoding(&self) -> crate::PositionEncoding {
+        self.encoding
+    }
+
+    /// Feeds a notification sent to the server.
+    pub(crate) fn track(&mut self, notif: &lsp_server::Notification) {
+        match notif.method.as_str() {
+            <lsp_types::notification::DidOpenTextDocument as Notification>::METHOD => {
+                if let Ok(params) = serde_json::from_value::<lsp_types::DidOpenTextDocumentParams>(
+                    notif.params.clone(),
+                ) {
+                    let document = Document {
+                        item: params.text_document,
+                        line_index: None,
+                    };
+                    self.documents.insert(document.item.uri.clone(), document);
+                }
+            }
+            <lsp_types::notification::DidChangeTextDocument as Notification>::METHOD => {
+                let params = match serde_json::from_value::<lsp_types::DidChangeTextDocumentParams>(
+                    notif.params.clone(),
+                ) {
+                    Ok(params) => params,
+                    Err(_) => return,
+                };
+                if let Some(document) = self.documents.get_mut(&params.text_document.uri) {
+                    document.item.version = params.text_document.version;
+                    document.line_index = None;
+                    for change in params.content_changes {
+                        apply_change(&mut document.item.text, change, self.encoding);
+                    }
+                }
+            }
+            <lsp_types::notification::DidCloseTextDocument as Notification>::METHOD => {
+                if let Ok(params) = serde_json::from_value::<lsp_types::DidCloseTextDocumentParams>(
+                    notif.params.clone(),
+                ) {
+                    self.documents.remove(&params.text_document.uri);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn get(&self, uri: &lsp_types::Url) -> Option<&lsp_types::TextDocumentItem> {
+        self.documents.get(uri).map(|document| &document.item)
+    }
+
+    /// The line index of the current text of `uri`, cached until it changes.
+    pub(crate) fn line_index(
+        &mut self,
+        uri: &lsp_types::Url,
+    ) -> Option<std::sync::Arc<crate::LineIndex>> {
+        let document = self.documents.get_mut(uri)?;
+        let line_index = document
+            .line_index
+            .get_or_insert_with(|| std::sync::Arc::new(crate::LineIndex::new(&document.item.text)));
+        Some(line_index.clone())
+    }
+
+    /// The `textDocument/didOpen` notifications re-opening every document.
+    pub(crate) fn reopen(
+        &self,
+    ) -> impl Iterator<Item = crate::Result<lsp_server::Notification>> + '_ {
+        self.documents.values().map(|document| {
+            crate::protocol::notification::<lsp_types::notification::DidOpenTextDocument>(
+                lsp_types::DidOpenTextDocumentParams {
+                    text_document: document.item.clone(),
+                },
+            )
+        })
+    }
+}
+
+/// Applies `change`, whose range is counted in `encoding`, to `text`.
+pub(crate) fn apply_change(
+    text: &mut String,
+    change: lsp_types::TextDocumentContentChangeEvent,
+    encoding: crate::PositionEncoding,
+) {
+    let range = match change.range {
+        Some(range) => range,
+        None => {
+            *text = change.text;
+            return;
+        }
+    };
+    let line_index = crate::LineIndex::new(text);
+    let start = line_index.offset_at(range.start, encoding);
+    let end = line_index.offset_at(range.end, encoding).max(start);
+    text.replace_range(start..end, &change.text);
+}
+
+#[test]
+fn track_open_documents() {
+    let uri = lsp_types::Url::parse("file:///lib.rs").unwrap();
+    let mut documents = OpenDocuments::default();
+    documents.track(
+        &crate::protocol::notification::<lsp_types::notification::DidOpenTextDocument>(
+            lsp_types::DidOpenTextDocumentParams {
+                text_document: lsp_types::TextDocumentItem::new(
+                    uri.clone(),
+                    "rust".to_string(),
+                    1,
+                    "fn a() {}\r\n// é😀\r\nfn b() {}\r\n".to_string(),
+                ),
+            },
+        )
+        .unwrap(),
+    );
+    let change = |range: Option<((u32, u32), (u32, u32))>, text: &str| {
+        lsp_types::TextDocumentContentChangeEvent {
+            range: range.map(|((l0, c0), (l1, c1))| {
+                lsp_types::Range::new(
+                    lsp_types::Position::new(l0, c0),
+                    lsp_types::Position::new(l1, c1),
+                )
+            }),
+            range_length: None,
+            text: text.to_string(),
+        }
+    };
+    documents.track(
+        &crate::protocol::notification::<lsp_types::notification::DidChangeTextDocument>(
+            lsp_types::DidChangeTextDocumentParams {
+                text_document: lsp_types::VersionedTextDocumentIdentifier::new(uri.clone(), 2),
+                content_changes: vec![
+                    // the emoji is two UTF-16 code units
+                    change(Some(((1, 3), (1, 6))), "x"),
+                    change(Some(((2, 3), (2, 4))), "c"),
+                    change(Some(((0, 99), (0, 99))), " // eol"),
+                ],
+            },
+        )
+        .unwrap(),
+    );
+    let reopened = documents.reopen().next().unwrap().unwrap();
+    assert_eq!(reopened.params["textDocument"]["version"], 2);
+    assert_eq!(
+        reopened.params["textDocument"]["text"],
+        "fn a() {} // eol\r\n// x\r\nfn c() {}\r\n"
+    );
+    let line_index = documents.line_index(&uri).unwrap();
+    assert_eq!(line_index.line_count(), 4);
+
+    // negotiated `positionEncoding: "utf-8"`, é is two columns
+    documents.set_encoding(crate::PositionEncoding::Utf8);
+    documents.track(
+        &crate::protocol::notification::<lsp_types::notification::DidChangeTextDocument>(
+            lsp_types::DidChangeTextDocumentParams {
+                text_document: lsp_types::VersionedTextDocumentIdentifier::new(uri.clone(), 3),
+                content_changes: vec![
+                    change(Some(((1, 3), (1, 4))), "é"),
+                    change(Some(((1, 5), (1, 5))), "!"),
+                ],
+            },
+        )
+        .unwrap(),
+    );
+    assert_eq!(
+        documents.get(&uri).unwrap().text.lines().nth(1),
+        Some("// é!")
+    );
+    assert!(!std::sync::Arc::ptr_eq(
+        &line_index,
+        &documents.line_index(&uri).unwrap()
+    ));
+
+    documents.track(
+        &crate::protocol::notification::<lsp_types::notification::DidCloseTextDocument>(
+            lsp_types::DidCloseTextDocumentParams {
+                text_document: lsp_types::TextDocumentIdentifier::new(uri),
+            },
+        )
+        .unwrap(),
+    );
+    assert_eq!(documents.reopen().count(), 0);
+}
+
+#[test]
+fn document_store() {
+    let mock = crate::mock::MockServer::new()
+        .initialize(serde_json::json!({
+            "textDocumentSync": { "openClose": true, "change": 2, "save": { "includeText": true } }
+        }))
+        .expect_notification("textDocument/didOpen")
+        .expect_notification("textDocument/didChange")
+        .expect_notification("textDocument/didSave");
+    let (client, mock) = (0..8)
+        .fold(mock, |mock, _| {
+            mock.expect_notification("textDocument/didChange")
+        })
+        .expect_notification("textDocument/didClose")
+        .start()
+        .unwrap();
+    let workspace = crate::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+    client.initialize(&workspace).unwrap();
+    // never written to disk
+    let uri = workspace.root_uri().join("src/overlay.rs").unwrap();
+    assert!(matches!(
+        client.document_text(&uri),
+        Err(crate::ClientError::DocumentNotOpen(_))
+    ));
+
+    client
+        .open_document(uri.clone(), "rust", "pub fn unused() {}\n".to_string())
+        .unwrap();
+    assert!(matches!(
+        client.open_document(uri.clone(), "rust", String::new()),
+        Err(crate::ClientError::DocumentAlreadyOpen(_))
+    ));
+    let deleted = lsp_types::TextDocumentContentChangeEvent {
+        range: Some(lsp_types::Range::new(
+            lsp_types::Position::new(0, 0),
+            lsp_types::Position::new(1, 0),
+        )),
+        range_length: None,
+        text: String::new(),
+    };
+    assert_eq!(client.change_document(&uri, vec![deleted]).unwrap(), 2);
+    assert_eq!(client.document_text(&uri).unwrap(), "");
+    client.save_document(&uri).unwrap();
+    // versions are handed out in the order the changes reach the server
+    std::thread::scope(|scope| {
+        for _ in 0..2 {
+            scope.spawn(|| {
+                for _ in 0..4 {
+                    let typed = lsp_types::TextDocumentContentChangeEvent {
+                        range: Some(lsp_types::Range::default()),
+                        range_length: None,
+                        text: "x".to_string(),
+                    };
+                    client.change_document(&uri, vec![typed]).unwrap();
+                }
+            });
+        }
+    });
+    assert_eq!(client.document_version(&uri).unwrap(), 10);
+    assert_eq!(client.document_text(&uri).unwrap(), "xxxxxxxx");
+    client.close_document(&uri).unwrap();
+    assert!(client.document_version(&uri).is_err());
+
+    let report = mock.finish();
+    report.assert_ok();
+    let changed = &report.notifications("textDocument/didChange")[0].params;
+    assert_eq!(changed["textDocument"]["version"], 2);
+    assert_eq!(changed["contentChanges"][0]["range"]["end"]["line"], 1);
+    assert_eq!(
+        report.notifications("textDocument/didSave")[0].params["text"],
+        ""
+    );
+    let versions: Vec<_> = report.notifications("textDocument/didChange")[1..]
+        .iter()
+        .map(|changed| changed.params["textDocument"]["version"].clone())
+        .collect();
+    assert_eq!(versions, (3..=10).collect::<Vec<_>>());
+
+    // a server syncing no changes only sees the document open and close
+    let (client, mock) = crate::mock::MockServer::new()
+        .initialize(serde_json::json!({ "textDocumentSync": 0 }))
+        .expect_notification("textDocument/didOpen")
+        .expect_notification("textDocument/didClose")
+        .start()
+        .unwrap();
+    client.initialize(&workspace).unwrap();
+    client
+        .open_document(uri.clone(), "rust", String::new())
+        .unwrap();
+    let typed = lsp_types::TextDocumentContentChangeEvent {
+        range: None,
+        range_length: None,
+        text: "fn typed() {}".to_string(),
+    };
+    assert_eq!(client.change_document(&uri, vec![typed]).unwrap(), 2);
+    assert_eq!(client.document_text(&uri).unwrap(), "fn typed() {}");
+    client.close_document(&uri).unwrap();
+    mock.finish().assert_ok();
+}
@@ -0,0 +1,125 @@
+/// Errors returned by [`Client`](crate::Client) methods.
+#[derive(Debug)]
+pub enum ClientError {
+    /// reading from or writing to the server pipes failed
+    Io(std::io::Error),
+    /// the server closed its stdout (EOF), usually because it exited or crashed
+    ServerExited,
+    /// the spawned server exited without being asked to
+    ServerDied {
+        /// `None` when the server closed its stdout but did not exit
+        status: Option<std::process::ExitStatus>,
+        /// its last stderr lines, usually a panic message
+        stderr: Vec<String>,
+    },
+    /// params or result could not be (de)serialized
+    Json(serde_json::Error),
+    /// the server answered with a JSON-RPC error response
+    Rpc {
+        code: i32,
+        message: String,
+        data: Option<serde_json::Value>,
+    },
+    /// the server sent a message the client did not expect at this point
+    UnexpectedMessage(Box<lsp_server::Message>),
+    /// the server did not answer in time, the request was cancelled with `$/cancelRequest`
+    Timeout,
+    /// the request was cancelled, the server answered with `RequestCanceled`
+    Cancelled,
+    /// the server answered with `ContentModified`, the result would be stale so retrying may succeed
+    ContentModified,
+    /// the path cannot be expressed as a `file://` url
+    InvalidPath(std::path::PathBuf),
+    /// no `Cargo.toml` in the directory or any of its parents
+    NoCargoWorkspace(std::path::PathBuf),
+    /// no working rust-analyzer binary, with the paths that were tried
+    ServerNotFound(Vec<std::path::PathBuf>),
+    /// the server binary is too old for the protocol extensions the client uses
+    IncompatibleServer { version: String, reason: String },
+    /// the server did not announce the capability this request method needs in `initialize`
+    Unsupported(String),
+    /// the document was not opened with [`Client::open_document`](crate::Client::open_document)
+    DocumentNotOpen(lsp_types::Url),
+    /// [`Client::open_document`](crate::Client::open_document) on a document already open
+    DocumentAlreadyOpen(lsp_types::Url),
+}
+
+pub type Result<T, E = ClientError> = std::result::Result<T, E>;
+
+impl std::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "lsp transport error: {err}"),
+            Self::ServerExited => write!(f, "lsp server exited"),
+            Self::ServerDied { status, stderr } => {
+                match status {
+                    Some(status) => write!(f, "lsp server died with {status}")?,
+                    None => write!(f, "lsp server closed its stdout")?,
+                }
+                for line in stderr {
+                    write!(f, "\n{line}")?;
+                }
+                Ok(())
+            }
+            Self::Json(err) => write!(f, "lsp json error: {err}"),
+            Self::Rpc { code, message, .. } => write!(f, "lsp server error {code}: {message}"),
+            Self::UnexpectedMessage(msg) => write!(f, "unexpected lsp message: {msg:?}"),
+            Self::Timeout => write!(f, "lsp request timed out"),
+            Self::Cancelled => write!(f, "lsp request cancelled"),
+            Self::ContentModified => write!(f, "lsp request result invalidated by content change"),
+            Self::InvalidPath(path) => write!(f, "invalid file path {}", path.display()),
+            Self::NoCargoWorkspace(path) => {
+                write!(f, "no cargo workspace contains {}", path.display())
+            }
+            Self::ServerNotFound(tried) => {
+                write!(f, "rust-analyzer not found, tried")?;
+                for path in tried {
+                    write!(f, " {}", path.display())?;
+                }
+                Ok(())
+            }
+            Self::IncompatibleServer { version, reason } => {
+                write!(f, "incompatible {version}: {reason}")
+            }
+            Self::Unsupported(method) => write!(f, "lsp server does not support {method}"),
+            Self::DocumentNotOpen(uri) => write!(f, "document {uri} is not open"),
+            Self::DocumentAlreadyOpen(uri) => write!(f, "document {uri} is already open"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl From<lsp_server::ResponseError> for ClientError {
+    fn from(err: lsp_server::ResponseError) -> Self {
+        match err.code {
+            code if code == lsp_server::ErrorCode::RequestCanceled as i32 => Self::Cancelled,
+            code if code == lsp_server::ErrorCode::ContentModified as i32 => Self::ContentModified,
+            code => Self::Rpc {
+                code,
+                message: err.message,
+                data: err.data,
+            },
+        }
+    }
+}
@@ -0,0 +1,223 @@
+/// A line and a column counted in UTF-8 bytes, both 0-based: what Rust string
+/// slicing understands, unlike an LSP [`Position`](lsp_types::Position).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LineCol {
+    pub line: u32,
+    pub col: u32,
+}
+
+/// The line layout of a text, converting between byte offsets, [`LineCol`]
+/// and LSP positions in any [`PositionEncoding`](crate::PositionEncoding).
+///
+/// Lines end with `\n` or `\r\n`, a column past the end of its line is
+/// clamped to the end, before the `\r`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// byte offset of the first char of every line
+    line_starts: Vec<usize>,
+    /// byte length of every line, without its `\n` or `\r\n`
+    line_lens: Vec<u32>,
+    /// the chars longer than one byte of every line that has some
+    wide_chars: std::collections::HashMap<u32, Vec<WideChar>>,
+    len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WideChar {
+    /// UTF-8 column of its first byte
+    col: u32,
+    len_utf8: u32,
+    len_utf16: u32,
+}
+
+impl WideChar {
+    fn len(&self, encoding: crate::PositionEncoding) -> u32 {
+        match encoding {
+            crate::PositionEncoding::Utf8 => self.len_utf8,
+            crate::PositionEncoding::Utf16 => self.len_utf16,
+            crate::PositionEncoding::Utf32 => 1,
+        }
+    }
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut line_lens = Vec::new();
+        let mut wide_chars = std::collections::HashMap::new();
+        for (line, text) in text.split('\n').enumerate() {
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            let wide: Vec<_> = text
+                .char_indices()
+                .filter(|(_, ch)| !ch.is_ascii())
+                .map(|(col, ch)| WideChar {
+                    col: col as u32,
+                    len_utf8: ch.len_utf8() as u32,
+                    len_utf16: ch.len_utf16() as u32,
+                })
+                .collect();
+            if !wide.is_empty() {
+                wide_chars.insert(line as u32, wide);
+            }
+            line_lens.push(text.len() as u32);
+        }
+        line_starts.extend(text.match_indices('\n').map(|(newline, _)| newline + 1));
+        Self {
+            line_starts,
+            line_lens,
+            wide_chars,
+            len: text.len(),
+        }
+    }
+
+    pub fn line_count(&self) -> u32 {
+        self.line_starts.len() as u32
+    }
+
+    /// The line and UTF-8 column of byte `offset`, clamped to the text.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let col = (offset - self.line_starts[line]).min(self.line_lens[line] as usize);
+        LineCol {
+            line: line as u32,
+            col: col as u32,
+        }
+    }
+
+    /// The byte offset of `line_col`, its column clamped to the end of its
+    /// line, `None` past the last line.
+    pub fn offset(&self, line_col: LineCol) -> Option<usize> {
+        let start = *self.line_starts.get(line_col.line as usize)?;
+        Some(start + line_col.col.min(self.line_lens[line_col.line as usize]) as usize)
+    }
+
+    /// The LSP position of `line_col`, its column counted in `encoding`. A
+    /// column in the middle of a char is rounded up to the next char.
+    pub fn position(
+        &self,
+        line_col: LineCol,
+        encoding: crate::PositionEncoding,
+    ) -> lsp_types::Position {
+        let wide_chars = self.wide_chars(line_col.line);
+        let col = wide_chars
+            .iter()
+            .find(|wide| wide.col < line_col.col && line_col.col < wide.col + wide.len_utf8)
+            .map_or(line_col.col, |wide| wide.col + wide.len_utf8);
+        let mut character = col;
+        for wide in wide_chars {
+            if wide.col >= col {
+                break;
+            }
+            character = character - wide.len_utf8 + wide.len(encoding);
+        }
+        lsp_types::Position::new(line_col.line, character)
+    }
+
+    /// The [`LineCol`] of an LSP `position` counted in `encoding`. A character
+    /// in the middle of a char is rounded up to the next char.
+    pub fn line_col_of(
+        &self,
+        position: lsp_types::Position,
+        encoding: crate::PositionEncoding,
+    ) -> LineCol {
+        let mut col = position.character;
+        for wide in self.wide_chars(position.line) {
+            // `col` is the UTF-8 column if every wide char before it was accounted for
+            if wide.col >= col {
+                break;
+            }
+            let len = wide.len(encoding);
+            if col - wide.col < len {
+                col = wide.col + wide.len_utf8;
+                break;
+            }
+            col = col - len + wide.len_utf8;
+        }
+        LineCol {
+            line: position.line,
+            col,
+        }
+    }
+
+    /// The byte offset of an LSP `position`, clamped to the end of its line,
+    /// or of the text past the last line.
+    pub fn offset_at(
+        &self,
+        position: lsp_types::Position,
+        encoding: crate::PositionEncoding,
+    ) -> usize {
+        let line_col = self.line_col_of(position, encoding);
+        self.offset(line_col).unwrap_or(self.len)
+    }
+
+    /// The LSP position of byte `offset`, counted in `encoding`.
+    pub fn position_at(
+        &self,
+        offset: usize,
+        encoding: crate::PositionEncoding,
+    ) -> lsp_types::Position {
+        self.position(self.line_col(offset), encoding)
+    }
+
+    fn wide_chars(&self, line: u32) -> &[WideChar] {
+        self.wide_chars.get(&line).map_or(&[], Vec::as_slice)
+    }
+}
+
+#[test]
+fn convert_positions() {
+    use crate::PositionEncoding::{Utf16, Utf32, Utf8};
+    let text = "fn a() {}\r\n// é😀x\r\nfn ñ() {}";
+    let index = LineIndex::new(text);
+    assert_eq!(index.line_count(), 3);
+
+    let x = text.find('x').unwrap();
+    let line_col = index.line_col(x);
+    assert_eq!(line_col, LineCol { line: 1, col: 9 });
+    assert_eq!(index.offset(line_col), Some(x));
+    // é is 2 UTF-8 bytes and 1 UTF-16 unit, 😀 4 bytes and 2 units
+    assert_eq!(
+        index.position(line_col, Utf8),
+        lsp_types::Position::new(1, 9)
+    );
+    assert_eq!(
+        index.position(line_col, Utf16),
+        lsp_types::Position::new(1, 6)
+    );
+    assert_eq!(
+        index.position(line_col, Utf32),
+        lsp_types::Position::new(1, 5)
+    );
+    for encoding in [Utf8, Utf16, Utf32] {
+        let position = index.position_at(x, encoding);
+        assert_eq!(index.offset_at(position, encoding), x);
+    }
+    // inside the emoji's surrogate pair
+    assert_eq!(
+        index.line_col_of(lsp_types::Position::new(1, 5), Utf16),
+        line_col
+    );
+
+    // past the end of a CRLF line, before its \r
+    let end = index.offset_at(lsp_types::Position::new(0, 99), Utf16);
+    assert_eq!(&text[..end], "fn a() {}");
+    assert_eq!(index.line_col(end + 1), LineCol { line: 0, col: 9 });
+    let n = text.find('ñ').unwrap();
+    assert_eq!(
+        index.position_at(n + 2, Utf16),
+        lsp_types::Position::new(2, 4)
+    );
+    assert_eq!(
+        index.offset_at(lsp_types::Position::new(9, 0), Utf16),
+        text.len()
+    );
+    // inside a char's UTF-8 bytes, rounded up to the next char
+    assert_eq!(
+        LineIndex::new("é").position_at(1, Utf16),
+        lsp_types::Position::new(0, 1)
+    );
+    assert_eq!(
+        index.position(LineCol { line: 1, col: 6 }, Utf32),
+        lsp_types::Position::new(1, 5)
+    );
+}
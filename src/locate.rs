@@ -0,0 +1,199 @@
+/// The oldest rust-analyzer speaking the protocol extensions the client relies on:
+/// `experimental/serverStatus` with `quiescent` landed on 2021-04-06.
+const MIN_RELEASE_DATE: (u32, u32, u32) = (2021, 4, 6);
+
+/// A rust-analyzer binary that answered `--version`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RustAnalyzer {
+    path: std::path::PathBuf,
+    version: RaVersion,
+}
+
+/// The parsed output of `rust-analyzer --version`, e.g.
+/// `rust-analyzer 0.3.1691-standalone (9b6ab8f5f 2023-10-08)` or
+/// `rust-analyzer 1.73.0 (cc66ad4 2023-10-03)` for the rustup component.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RaVersion {
+    /// the whole first line
+    pub raw: String,
+    /// `0.3.1691-standalone` or the toolchain version
+    pub release: Option<String>,
+    pub commit: Option<String>,
+    /// (year, month, day) of the commit
+    pub date: Option<(u32, u32, u32)>,
+}
+
+impl RustAnalyzer {
+    /// Finds rust-analyzer, trying in order `explicit`, the `RUST_ANALYZER`
+    /// env var, `rustup which rust-analyzer`, `~/.cargo/bin` and `PATH`. The
+    /// first binary answering `--version` is used: it is an error when it is
+    /// older than the protocol extensions the client uses, a `tracing` warning
+    /// when its version cannot be told.
+    pub fn locate(explicit: Option<&std::path::Path>) -> crate::Result<Self> {
+        if let Some(explicit) = explicit {
+            return Self::at(explicit);
+        }
+        let mut tried = Vec::new();
+        for candidate in candidates() {
+            if tried.contains(&candidate) {
+                continue;
+            }
+            match Self::at(&candidate) {
+                Ok(found) => return Ok(found),
+                // the ~/.cargo/bin rustup proxy exists even without the component installed
+                Err(crate::ClientError::Io(_)) | Err(crate::ClientError::ServerNotFound(_)) => {
+                    tried.push(candidate)
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(crate::ClientError::ServerNotFound(tried))
+    }
+
+    /// Checks the rust-analyzer binary at `path`, see [`RustAnalyzer::locate`].
+    pub fn at(path: impl AsRef<std::path::Path>) -> crate::Result<Self> {
+        let path = path.as_ref();
+        let output = std::process::Command::new(path)
+            .arg("--version")
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .output()?;
+        if !output.status.success() {
+            return Err(crate::ClientError::ServerNotFound(vec![path.to_path_buf()]));
+        }
+        let version = RaVersion::parse(&String::from_utf8_lossy(&output.stdout));
+        match version.date {
+            Some(date) if date < MIN_RELEASE_DATE => {
+                return Err(crate::ClientError::IncompatibleServer {
+                    version: version.raw,
+                    reason: format!(
+                        "released before {}-{:02}-{:02}, it lacks experimental/serverStatus",
+                        MIN_RELEASE_DATE.0, MIN_RELEASE_DATE.1, MIN_RELEASE_DATE.2
+                    ),
+                })
+            }
+            Some(_) => {}
+            None => tracing::warn!(
+                "cannot tell the release date of {} from {:?}, assuming it is compatible",
+                path.display(),
+                version.raw
+            ),
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            version,
+        })
+    }
+
+    pub fn path(&self) -> &std::path::Path {
+        &self.path
+    }
+
+    pub fn version(&self) -> &RaVersion {
+        &self.version
+    }
+
+    /// A command running this binary, to pass to [`Client::spawn`](crate::Client::spawn).
+    pub fn command(&self) -> std::process::Command {
+        std::process::Command::new(&self.path)
+    }
+}
+
+impl RaVersion {
+    pub fn parse(output: &str) -> Self {
+        let raw = output.lines().next().unwrap_or_default().trim().to_string();
+        let mut release = None;
+        let mut commit = None;
+        let mut date = None;
+        for word in raw
+            .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+            .filter(|word| !word.is_empty())
+            .skip(1)
+        {
+            if let Some(parsed) = parse_date(word) {
+                date.get_or_insert(parsed);
+            } else if word.starts_with(|c: char| c.is_ascii_digit()) && word.contains('.') {
+                release.get_or_insert_with(|| word.to_string());
+            } else if (7..=40).contains(&word.len()) && word.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                commit.get_or_insert_with(|| word.to_string());
+            }
+        }
+        Self {
+            raw,
+            release,
+            commit,
+            date,
+        }
+    }
+}
+
+/// `YYYY-MM-DD`
+fn parse_date(word: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = word.splitn(3, '-');
+    let (year, month, day) = (parts.next()?, parts.next()?, parts.next()?);
+    if year.len() != 4 || month.len() != 2 || day.len() != 2 {
+        return None;
+    }
+    Some((year.parse().ok()?, month.parse().ok()?, day.parse().ok()?))
+}
+
+fn candidates() -> Vec<std::path::PathBuf> {
+    let exe = format!("rust-analyzer{}", std::env::consts::EXE_SUFFIX);
+    let mut candidates = Vec::new();
+    if let Some(path) = std::env::var_os("RUST_ANALYZER") {
+        candidates.push(path.into());
+    }
+    if let Ok(output) = std::process::Command::new("rustup")
+        .args(["which", "rust-analyzer"])
+        .stderr(std::process::Stdio::null())
+        .output()
+    {
+        let path = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if output.status.success() && !path.is_empty() {
+            candidates.push(path.into());
+        }
+    }
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(std::path::PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(|home| std::path::PathBuf::from(home).join(".cargo"))
+        });
+    if let Some(cargo_home) = cargo_home {
+        let path = cargo_home.join("bin").join(&exe);
+        if path.is_file() {
+            candidates.push(path);
+        }
+    }
+    if let Some(paths) = std::env::var_os("PATH") {
+        candidates.extend(
+            std::env::split_paths(&paths)
+                .map(|dir| dir.join(&exe))
+                .filter(|path| path.is_file()),
+        );
+    }
+    candidates
+}
+
+#[test]
+fn parse_ra_version() {
+    let standalone = RaVersion::parse("rust-analyzer 0.3.1691-standalone (9b6ab8f5f 2023-10-08)\n");
+    assert_eq!(standalone.release.as_deref(), Some("0.3.1691-standalone"));
+    assert_eq!(standalone.commit.as_deref(), Some("9b6ab8f5f"));
+    assert_eq!(standalone.date, Some((2023, 10, 8)));
+
+    let component = RaVersion::parse("rust-analyzer 1.73.0 (cc66ad4 2023-10-03)");
+    assert_eq!(component.release.as_deref(), Some("1.73.0"));
+    assert_eq!(component.commit.as_deref(), Some("cc66ad4"));
+
+    // the format before releases had a version number
+    let nightly = RaVersion::parse("rust-analyzer a670ff888 2022-06-13 nightly");
+    assert_eq!(nightly.release, None);
+    assert_eq!(nightly.commit.as_deref(), Some("a670ff888"));
+    assert_eq!(nightly.date, Some((2022, 6, 13)));
+    assert!(nightly.date.unwrap() >= MIN_RELEASE_DATE);
+
+    assert_eq!(RaVersion::parse("rust-analyzer").date, None);
+}
@@ -0,0 +1,586 @@
+//! A scripted language server for testing clients offline, without rust-analyzer.
+//!
+//! The script is played in order: steps expecting a message from the client
+//! block until it arrives, anything else the client sends meanwhile is recorded
+//! as an error. The server hangs up once the script is done.
+//!
+//! ```
+//! use lsp_client::mock::{MockServer, Reply};
+//!
+//! let (client, mock) = MockServer::new()
+//!     .initialize(serde_json::json!({ "hoverProvider": true }))
+//!     .expect_request("textDocument/hover", Reply::Result(serde_json::Value::Null))
+//!     .shutdown()
+//!     .start()
+//!     .unwrap();
+//! let workspace = lsp_client::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+//! client.init(&workspace).unwrap();
+//! let uri = workspace.root_uri().join("src/lib.rs").unwrap();
+//! let hover = client
+//!     .request::<lsp_types::request::HoverRequest>(lsp_types::HoverParams {
+//!         text_document_position_params: lsp_types::TextDocumentPositionParams::new(
+//!             lsp_types::TextDocumentIdentifier::new(uri),
+//!             lsp_types::Position::new(0, 0),
+//!         ),
+//!         work_done_progress_params: Default::default(),
+//!     })
+//!     .unwrap();
+//! assert_eq!(hover, None);
+//! client.exit().unwrap();
+//! mock.finish().assert_ok();
+//! ```
+//!
+//! The `lsp-mock` binary serves a script saved as JSON on its stdio, for
+//! clients that have to spawn their server.
+
+/// How long a step waits for the client by default.
+const DEFAULT_STEP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// A script of messages to expect from and send to the client.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct MockServer {
+    steps: Vec<Step>,
+    /// how long a step waits for the client before the mock gives up and hangs up
+    timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "step", rename_all = "camelCase")]
+pub enum Step {
+    /// waits for request `method` and answers it with `reply`, or holds it
+    /// for a later [`Step::Reply`] when `None`
+    Request {
+        method: String,
+        reply: Option<Reply>,
+        /// names the held request for [`Step::Reply`], e.g. the id it had in a transcript
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<lsp_server::RequestId>,
+    },
+    /// answers the held request named `id`, or else the oldest held one of
+    /// `method`, out of order with the requests received since
+    Reply {
+        method: String,
+        reply: Reply,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<lsp_server::RequestId>,
+    },
+    /// waits for notification `method`
+    Notification {
+        method: String,
+    },
+    /// sends notification `method`
+    Notify {
+        method: String,
+        params: serde_json::Value,
+    },
+    /// sends request `method`
+    ServerRequest {
+        method: String,
+        params: serde_json::Value,
+    },
+    /// waits for the client's response to the oldest unanswered request of `method`
+    Response {
+        method: String,
+    },
+    Sleep {
+        millis: u64,
+    },
+    /// closes the connection, the client sees EOF with its requests still pending
+    HangUp,
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Reply {
+    Result(serde_json::Value),
+    /// e.g. `ErrorCode::ContentModified as i32`
+    Error {
+        code: i32,
+        message: String,
+    },
+}
+
+/// What the client sent to a [`MockServer`] and where it strayed from the script.
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct MockReport {
+    /// every message from the client, in order
+    pub received: Vec<lsp_server::Message>,
+    /// unexpected messages, steps that never happened
+    pub errors: Vec<String>,
+}
+
+/// The thread serving a [`MockServer`] started by [`MockServer::start`].
+pub struct MockHandle {
+    thread: std::thread::JoinHandle<MockReport>,
+}
+
+impl Default for MockServer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockServer {
+    pub fn new() -> Self {
+        Self {
+            steps: Vec::new(),
+            timeout_ms: DEFAULT_STEP_TIMEOUT.as_millis() as u64,
+        }
+    }
+
+    /// Sets how long a step waits for the client, 10 seconds by default.
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout_ms = timeout.as_millis() as u64;
+        self
+    }
+
+    pub fn step(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Expects `initialize`, answers it with `capabilities`, then expects `initialized`.
+    pub fn initialize(self, capabilities: serde_json::Value) -> Self {
+        self.expect_request(
+            "initialize",
+            Reply::Result(serde_json::json!({ "capabilities": capabilities })),
+        )
+        .expect_notification("initialized")
+    }
+
+    pub fn expect_request(self, method: &str, reply: Reply) -> Self {
+        self.step(Step::Request {
+            method: method.to_string(),
+            reply: Some(reply),
+            id: None,
+        })
+    }
+
+    /// Expects request `method` without answering it yet, see [`MockServer::reply`].
+    pub fn hold_request(self, method: &str) -> Self {
+        self.step(Step::Request {
+            method: method.to_string(),
+            reply: None,
+            id: None,
+        })
+    }
+
+    /// Answers the oldest request of `method` held by [`MockServer::hold_request`].
+    pub fn reply(self, method: &str, reply: Reply) -> Self {
+        self.step(Step::Reply {
+            method: method.to_string(),
+            reply,
+            id: None,
+        })
+    }
+
+    pub fn expect_notification(self, method: &str) -> Self {
+        self.step(Step::Notification {
+            method: method.to_string(),
+        })
+    }
+
+    pub fn notify(self, method: &str, params: serde_json::Value) -> Self {
+        self.step(Step::Notify {
+            method: method.to_string(),
+            params,
+        })
+    }
+
+    /// Sends request `method` and waits for the client's response, found in
+    /// [`MockReport::received`].
+    pub fn server_request(self, method: &str, params: serde_json::Value) -> Self {
+        self.step(Step::ServerRequest {
+            method: method.to_string(),
+            params,
+        })
+        .step(Step::Response {
+            method: method.to_string(),
+        })
+    }
+
+    pub fn sleep(self, duration: std::time::Duration) -> Self {
+        self.step(Step::Sleep {
+            millis: duration.as_millis() as u64,
+        })
+    }
+
+    /// Expects `shutdown`, answers it, then expects `exit`.
+    pub fn shutdown(self) -> Self {
+        self.expect_request("shutdown", Reply::Result(serde_json::Value::Null))
+            .expect_notification("exit")
+    }
+
+    /// Closes the connection here, abruptly if requests are still held. Steps after it are not played.
+    pub fn hang_up(self) -> Self {
+        self.step(Step::HangUp)
+    }
+
+    /// Plays back a transcript recorded by [`Client::set_transcript`](crate::Client::set_transcript):
+    /// what the server sent is sent again, in the same order relative to what
+    /// the client is expected to send. Timing is not reproduced.
+    pub fn replay(transcript: impl IntoIterator<Item = crate::TranscriptEntry>) -> Self {
+        let mut mock = Self::new();
+        // the methods of the recorded requests, to tell what a response answers
+        let mut methods = std::collections::HashMap::new();
+        for entry in transcript {
+            let step = match (entry.direction, entry.message) {
+                (crate::Direction::ToServer, lsp_server::Message::Request(req)) => {
+                    methods.insert(req.id.clone(), req.method.clone());
+                    Step::Request {
+                        method: req.method,
+                        reply: None,
+                        id: Some(req.id),
+                    }
+                }
+                (crate::Direction::ToServer, lsp_server::Message::Notification(notif)) => {
+                    Step::Notification {
+                        method: notif.method,
+                    }
+                }
+                (crate::Direction::ToServer, lsp_server::Message::Response(_)) => continue,
+                (crate::Direction::FromServer, lsp_server::Message::Request(req)) => {
+                    mock.steps.push(Step::ServerRequest {
+                        method: req.method.clone(),
+                        params: req.params,
+                    });
+                    Step::Response { method: req.method }
+                }
+                (crate::Direction::FromServer, lsp_server::Message::Notification(notif)) => {
+                    Step::Notify {
+                        method: notif.method,
+                        params: notif.params,
+                    }
+                }
+                (crate::Direction::FromServer, lsp_server::Message::Response(rsp)) => {
+                    let method = match methods.get(&rsp.id) {
+                        Some(method) => method.clone(),
+                        None => continue,
+                    };
+                    let reply = match rsp.error {
+                        Some(err) => Reply::Error {
+                            code: err.code,
+                            message: err.message,
+                        },
+                        None => Reply::Result(rsp.result.unwrap_or_default()),
+                    };
+                    Step::Reply {
+                        method,
+                        reply,
+                        id: Some(rsp.id),
+                    }
+                }
+            };
+            mock.steps.push(step);
+        }
+        mock
+    }
+
+    /// Serves the script on a thread, to a client talking to it in-process
+    /// with a [`GenericProfile`](crate::GenericProfile) named `mock`.
+    pub fn start(self) -> crate::Result<(crate::Client, MockHandle)> {
+        let (client, server) = lsp_server::Connection::memory();
+        let thread = std::thread::Builder::new()
+            .name("lsp_client mock server".to_string())
+            .spawn(move || self.serve(server))?;
+        let mut client = crate::Client::from_connection(client)?;
+        client.set_profile(crate::GenericProfile::new("mock", [] as [&str; 0]));
+        Ok((client, MockHandle { thread }))
+    }
+
+    /// Plays the script to the client on the other end of `conn`.
+    pub fn serve(self, conn: lsp_server::Connection) -> MockReport {
+        let to_client = crate::transport::MessageWriter::Channel(conn.sender);
+        self.play(to_client, conn.receiver)
+    }
+
+    /// Plays the script to the client that spawned this process, on stdio.
+    pub fn serve_stdio(self) -> MockReport {
+        let (msg_tx, msg_rx) = crossbeam_channel::unbounded();
+        // left blocked on stdin once the script is done, until the process exits
+        std::thread::spawn(move || {
+            let mut stdin = crate::transport::MessageReader::new(std::io::stdin());
+            while let Ok(Some(msg)) = stdin.read() {
+                if msg_tx.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+        self.play(
+            crate::transport::MessageWriter::new(std::io::stdout()),
+            msg_rx,
+        )
+    }
+
+    fn play(
+        self,
+        to_client: crate::transport::MessageWriter,
+        from_client: crossbeam_channel::Receiver<lsp_server::Message>,
+    ) -> MockReport {
+        let mut session = Session {
+            to_client,
+            from_client,
+            timeout: std::time::Duration::from_millis(self.timeout_ms),
+            held: Vec::new(),
+            sent: Vec::new(),
+            next_id: 0,
+            report: MockReport::default(),
+        };
+        for step in self.steps {
+            if session.play(step).is_none() {
+                break;
+            }
+        }
+        session.report
+    }
+}
+
+impl Reply {
+    fn into_response(self, id: lsp_server::RequestId) -> lsp_server::Response {
+        match self {
+            Reply::Result(result) => lsp_server::Response::new_ok(id, result),
+            Reply::Error { code, message } => lsp_server::Response::new_err(id, code, message),
+        }
+    }
+}
+
+impl MockReport {
+    /// Panics listing the errors, if any.
+    pub fn assert_ok(&self) {
+        assert!(
+            self.errors.is_empty(),
+            "the client strayed from the mock script:\n{}",
+            self.errors.join("\n")
+        );
+    }
+
+    /// The requests of `method` the client sent.
+    pub fn requests(&self, method: &str) -> Vec<&lsp_server::Request> {
+        self.received
+            .iter()
+            .filter_map(|msg| match msg {
+                lsp_server::Message::Request(req) if req.method == method => Some(req),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The notifications of `method` the client sent.
+    pub fn notifications(&self, method: &str) -> Vec<&lsp_server::Notification> {
+        self.received
+            .iter()
+            .filter_map(|msg| match msg {
+                lsp_server::Message::Notification(notif) if notif.method == method => Some(notif),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+impl MockHandle {
+    /// Waits for the script to be done and returns what the client sent.
+    pub fn finish(self) -> MockReport {
+        match self.thread.join() {
+            Ok(report) => report,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+struct Session {
+    to_client: crate::transport::MessageWriter,
+    from_client: crossbeam_channel::Receiver<lsp_server::Message>,
+    timeout: std::time::Duration,
+    /// requests answered by a later [`Step::Reply`], with the name they were held under
+    held: Vec<(Option<lsp_server::RequestId>, lsp_server::Request)>,
+    /// server requests waiting for a [`Step::Response`]
+    sent: Vec<lsp_server::Request>,
+    next_id: i32,
+    report: MockReport,
+}
+
+impl Session {
+    /// `None` hangs up.
+    fn play(&mut self, step: Step) -> Option<()> {
+        match step {
+            Step::Request { method, reply, id } => {
+                let req = self.expect(&format!("request {method}"), |msg| match msg {
+                    lsp_server::Message::Request(req) if req.method == method => Some(req.clone()),
+                    _ => None,
+                })?;
+                match reply {
+                    Some(reply) => self.send(reply.into_response(req.id).into()),
+                    None => {
+                        self.held.push((id, req));
+                        Some(())
+                    }
+                }
+            }
+            Step::Reply { method, reply, id } => {
+                let held = match &id {
+                    Some(id) => self
+                        .held
+                        .iter()
+                        .position(|(name, _)| name.as_ref() == Some(id)),
+                    None => self.held.iter().position(|(_, req)| req.method == method),
+                };
+                match held {
+                    Some(index) => {
+                        let (_, req) = self.held.remove(index);
+                        self.send(reply.into_response(req.id).into())
+                    }
+                    None => {
+                        let error = format!("script error: no held request {method} to reply to");
+                        self.report.errors.push(error);
+                        Some(())
+                    }
+                }
+            }
+            Step::Notification { method } => {
+                self.expect(&format!("notification {method}"), |msg| match msg {
+                    lsp_server::Message::Notification(notif) if notif.method == method => Some(()),
+                    _ => None,
+                })
+            }
+            Step::Notify { method, params } => {
+                self.send(lsp_server::Notification { method, params }.into())
+            }
+            Step::ServerRequest { method, params } => {
+                self.next_id += 1;
+                let id = lsp_server::RequestId::from(format!("mock/{}", self.next_id));
+                let req = lsp_server::Request { id, method, params };
+                self.sent.push(req.clone());
+                self.send(req.into())
+            }
+            Step::Response { method } => {
+                let index = match self.sent.iter().position(|req| req.method == method) {
+                    Some(index) => index,
+                    None => {
+                        let error = format!("script error: no request {method} sent to the client");
+                        self.report.errors.push(error);
+                        return Some(());
+                    }
+                };
+                let id = self.sent.remove(index).id;
+                self.expect(&format!("response to {method}"), |msg| match msg {
+                    lsp_server::Message::Response(rsp) if rsp.id == id => Some(()),
+                    _ => None,
+                })
+            }
+            Step::Sleep { millis } => {
+                std::thread::sleep(std::time::Duration::from_millis(millis));
+                Some(())
+            }
+            Step::HangUp => None,
+        }
+    }
+
+    /// Receives messages until one `matches`, recording the others as errors.
+    fn expect<T>(
+        &mut self,
+        expected: &str,
+        matches: impl Fn(&lsp_server::Message) -> Option<T>,
+    ) -> Option<T> {
+        loop {
+            let msg = match self.from_client.recv_timeout(self.timeout) {
+                Ok(msg) => msg,
+                Err(crossbeam_channel::RecvTimeoutError::Timeout) => {
+                    let error =
+                        format!("timed out after {:?} waiting for {expected}", self.timeout);
+                    self.report.errors.push(error);
+                    return None;
+                }
+                Err(crossbeam_channel::RecvTimeoutError::Disconnected) => {
+                    let error = format!("the client hung up before {expected}");
+                    self.report.errors.push(error);
+                    return None;
+                }
+            };
+            let matched = matches(&msg);
+            if matched.is_none() {
+                let error = format!("expected {expected}, got {}", describe(&msg));
+                self.report.errors.push(error);
+            }
+            self.report.received.push(msg);
+            if matched.is_some() {
+                return matched;
+            }
+        }
+    }
+
+    fn send(&mut self, msg: lsp_server::Message) -> Option<()> {
+        match self.to_client.write(msg) {
+            Ok(()) => Some(()),
+            Err(err) => {
+                self.report
+                    .errors
+                    .push(format!("cannot send to the client: {err}"));
+                None
+            }
+        }
+    }
+}
+
+fn describe(msg: &lsp_server::Message) -> String {
+    match msg {
+        lsp_server::Message::Request(req) => format!("request {} {:?}", req.method, req.id),
+        lsp_server::Message::Response(rsp) => format!("response {:?}", rsp.id),
+        lsp_server::Message::Notification(notif) => format!("notification {}", notif.method),
+    }
+}
+
+#[test]
+fn mock_script() {
+    let content_modified = lsp_server::ErrorCode::ContentModified as i32;
+    let (client, mock) = MockServer::new()
+        .initialize(serde_json::json!({ "referencesProvider": true }))
+        .server_request(
+            "workspace/configuration",
+            serde_json::json!({ "items": [{ "section": "mock" }] }),
+        )
+        .notify(
+            "window/logMessage",
+            serde_json::json!({ "type": 3, "message": "loaded" }),
+        )
+        .hold_request("first")
+        .expect_request("second", Reply::Result(serde_json::json!(2)))
+        .sleep(std::time::Duration::from_millis(10))
+        .reply("first", Reply::Result(serde_json::json!(1)))
+        .expect_request(
+            "third",
+            Reply::Error {
+                code: content_modified,
+                message: "modified".to_string(),
+            },
+        )
+        .hold_request("fourth")
+        .hang_up()
+        .start()
+        .unwrap();
+    let log = client.subscribe("window/logMessage");
+    let workspace = crate::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+    client.init(&workspace).unwrap();
+    assert_eq!(log.recv().unwrap().params["message"], "loaded");
+
+    let req = |method: &str| lsp_server::Request::new(client.next_id(), method.to_string(), ());
+    let first = client.send(req("first")).unwrap();
+    let second = client.send(req("second")).unwrap();
+    assert_eq!(second.wait().unwrap(), Some(serde_json::json!(2)));
+    assert_eq!(first.wait().unwrap(), Some(serde_json::json!(1)));
+    assert!(matches!(
+        client.send_req(req("third")),
+        Err(crate::ClientError::ContentModified)
+    ));
+    assert!(matches!(
+        client.send_req(req("fourth")),
+        Err(crate::ClientError::ServerExited)
+    ));
+
+    let report = mock.finish();
+    report.assert_ok();
+    assert_eq!(report.requests("initialize").len(), 1);
+    assert!(report.received.iter().any(|msg| matches!(
+        msg,
+        lsp_server::Message::Response(rsp) if rsp.result == Some(serde_json::json!([null]))
+    )));
+}
@@ -0,0 +1,349 @@
+//! The language server child process owned by [`Client::spawn`](crate::Client::spawn).
+
+/// stderr lines kept to explain an unexpected exit, unless [`StderrPolicy::Capture`] says otherwise
+const STDERR_TAIL_LINES: usize = 20;
+
+/// How long [`Client::exit`](crate::Client::exit) waits for the server after
+/// `exit`, and again after SIGTERM, before killing it.
+pub(crate) const DEFAULT_SHUTDOWN_GRACE: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// How long to wait for the exit status and last stderr lines once the server
+/// closed its stdout.
+const EXIT_REPORT_WAIT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// What [`ServerProcess::unexpected_exit`] reports: the exit status, `None`
+/// while the server still runs, and its last stderr lines.
+pub(crate) type ExitReport = (Option<std::process::ExitStatus>, Vec<String>);
+
+/// The error for requests to a server that is gone, [`ClientError::ServerDied`](crate::ClientError::ServerDied)
+/// when it exited without being asked to.
+pub(crate) fn exit_error(report: &Option<ExitReport>) -> crate::ClientError {
+    match report {
+        Some((status, stderr)) => crate::ClientError::ServerDied {
+            status: *status,
+            stderr: stderr.clone(),
+        },
+        None => crate::ClientError::ServerExited,
+    }
+}
+
+/// Where the stderr of a spawned server goes, set by
+/// [`ServerProfile::stderr`](crate::ServerProfile::stderr) or given to
+/// [`Client::spawn_with_stderr`](crate::Client::spawn_with_stderr) and
+/// [`Client::spawn_supervised`](crate::Client::spawn_supervised).
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum StderrPolicy {
+    /// forwarded to `tracing` line by line, the last 20 lines kept for crash reports
+    #[default]
+    Tracing,
+    /// written to our own stderr, crash reports have no stderr lines
+    Inherit,
+    Discard,
+    /// appended to this file, created along with its parent directories
+    File(std::path::PathBuf),
+    /// only kept in a ring buffer of this many lines, read by
+    /// [`Client::stderr_tail`](crate::Client::stderr_tail) and crash reports
+    Capture(usize),
+}
+
+impl StderrPolicy {
+    /// How to spawn the server's stderr and, when it is piped, where its lines go.
+    pub(crate) fn stdio(&self) -> crate::Result<(std::process::Stdio, Option<StderrTail>)> {
+        let tail = |capacity, forward| StderrTail {
+            lines: std::sync::Arc::default(),
+            capacity,
+            forward,
+        };
+        Ok(match self {
+            StderrPolicy::Tracing => (
+                std::process::Stdio::piped(),
+                Some(tail(STDERR_TAIL_LINES, true)),
+            ),
+            StderrPolicy::Inherit => (std::process::Stdio::inherit(), None),
+            StderrPolicy::Discard => (std::process::Stdio::null(), None),
+            StderrPolicy::File(path) => {
+                if let Some(dir) = path.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                let file = std::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)?;
+                (file.into(), None)
+            }
+            StderrPolicy::Capture(lines) => {
+                (std::process::Stdio::piped(), Some(tail(*lines, false)))
+            }
+        })
+    }
+}
+
+/// The last stderr lines of a server, shared with the thread or task reading them.
+#[derive(Clone)]
+pub(crate) struct StderrTail {
+    lines: std::sync::Arc<std::sync::Mutex<std::collections::VecDeque<String>>>,
+    capacity: usize,
+    /// forward every line to `tracing` too
+    forward: bool,
+}
+
+impl StderrTail {
+    pub(crate) fn push(&self, line: String) {
+        if self.forward {
+            crate::trace::stderr_line(&line);
+        }
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        if self.capacity > 0 {
+            lines.push_back(line);
+        }
+    }
+
+    pub(crate) fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+pub(crate) struct ServerProcess {
+    child: std::process::Child,
+    /// `None` unless stderr is piped to us
+    stderr_tail: Option<StderrTail>,
+    stderr_reader: Option<std::thread::JoinHandle<()>>,
+    /// set once `shutdown` was sent, the server exiting is expected from then on
+    shutting_down: bool,
+}
+
+impl ServerProcess {
+    /// Spawns `command` with stdin and stdout piped, and stderr going where
+    /// `stderr` says. Piped stderr lines are kept for [`ServerProcess::unexpected_exit`].
+    pub(crate) fn spawn(
+        command: &mut std::process::Command,
+        stderr: &StderrPolicy,
+    ) -> crate::Result<(Self, std::process::ChildStdin, std::process::ChildStdout)> {
+        let (stderr, stderr_tail) = stderr.stdio()?;
+        let mut child = command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(stderr)
+            .spawn()?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(crate::ClientError::ServerExited);
+            }
+        };
+        let stderr_reader = match (&stderr_tail, child.stderr.take()) {
+            (Some(tail), Some(stderr)) => {
+                let tail = tail.clone();
+                let reader = std::thread::Builder::new()
+                    .name("lsp_client stderr".to_string())
+                    .spawn(move || {
+                        use std::io::BufRead;
+                        for line in std::io::BufReader::new(stderr).lines() {
+                            match line {
+                                Ok(line) => tail.push(line),
+                                Err(_) => break,
+                            }
+                        }
+                    })?;
+                Some(reader)
+            }
+            _ => None,
+        };
+        Ok((
+            Self {
+                child,
+                stderr_tail,
+                stderr_reader,
+                shutting_down: false,
+            },
+            stdin,
+            stdout,
+        ))
+    }
+
+    pub(crate) fn set_shutting_down(&mut self) {
+        self.shutting_down = true;
+    }
+
+    /// The exit status and last stderr lines when the server exited without
+    /// being asked to, `None` after `shutdown`.
+    pub(crate) fn unexpected_exit(&mut self) -> Option<ExitReport> {
+        if self.shutting_down {
+            return None;
+        }
+        let status = self.wait_timeout(EXIT_REPORT_WAIT).ok().flatten();
+        // the last lines, usually the panic message, may still be in the pipe
+        let start = std::time::Instant::now();
+        while self
+            .stderr_reader
+            .as_ref()
+            .is_some_and(|reader| !reader.is_finished())
+            && start.elapsed() < EXIT_REPORT_WAIT
+        {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        Some((status, self.stderr_tail()))
+    }
+
+    pub(crate) fn stderr_tail(&self) -> Vec<String> {
+        self.stderr_tail
+            .as_ref()
+            .map(StderrTail::lines)
+            .unwrap_or_default()
+    }
+
+    /// Waits `grace` for the server to exit on its own, then sends SIGTERM and
+    /// waits `grace` again before killing it.
+    pub(crate) fn terminate(
+        &mut self,
+        grace: std::time::Duration,
+    ) -> crate::Result<std::process::ExitStatus> {
+        self.shutting_down = true;
+        if let Some(status) = self.wait_timeout(grace)? {
+            return Ok(status);
+        }
+        // without `kill` on PATH the server is still killed below
+        if let Err(err) = sigterm(self.child.id()) {
+            tracing::warn!("cannot send SIGTERM to the server: {err}");
+        }
+        if let Some(status) = self.wait_timeout(grace)? {
+            return Ok(status);
+        }
+        self.child.kill()?;
+        Ok(self.child.wait()?)
+    }
+
+    /// Ends the server once `shutdown` and `exit` were sent, `exit` telling how
+    /// that went: the server is terminated even if it no longer answers. Fails
+    /// with [`ClientError::ServerDied`](crate::ClientError::ServerDied) when it
+    /// did not exit successfully.
+    pub(crate) fn finish(
+        &mut self,
+        grace: std::time::Duration,
+        exit: crate::Result<()>,
+    ) -> crate::Result<std::process::ExitStatus> {
+        let status = self.terminate(grace)?;
+        if !status.success() {
+            return Err(crate::ClientError::ServerDied {
+                status: Some(status),
+                stderr: self.stderr_tail(),
+            });
+        }
+        exit.map(|()| status)
+    }
+
+    /// Kills the server unless it already exited.
+    pub(crate) fn kill(&mut self) {
+        self.shutting_down = true;
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    fn wait_timeout(
+        &mut self,
+        timeout: std::time::Duration,
+    ) -> std::io::Result<Option<std::process::ExitStatus>> {
+        let start = std::time::Instant::now();
+        loop {
+            if let Some(status) = self.child.try_wait()? {
+                return Ok(Some(status));
+            }
+            if start.elapsed() >= timeout {
+                return Ok(None);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+/// std can only SIGKILL a child, the `kill` utility sends SIGTERM without any
+/// unsafe libc call.
+#[cfg(unix)]
+pub(crate) fn sigterm(pid: u32) -> std::io::Result<()> {
+    std::process::Command::new("kill")
+        .arg("-TERM")
+        .arg(pid.to_string())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()?;
+    Ok(())
+}
+
+/// `taskkill` without `/F` asks the process to close, like SIGTERM.
+#[cfg(windows)]
+pub(crate) fn sigterm(pid: u32) -> std::io::Result<()> {
+    std::process::Command::new("taskkill")
+        .arg("/PID")
+        .arg(pid.to_string())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn report_unexpected_exit() {
+    let mut command = std::process::Command::new("sh");
+    command.args(["-c", "echo 'thread main panicked' >&2; exit 101"]);
+    let client = crate::Client::spawn(command).unwrap();
+    match client.request::<lsp_types::request::Shutdown>(()) {
+        Err(crate::ClientError::ServerDied { status, stderr }) => {
+            assert_eq!(status.unwrap().code(), Some(101));
+            assert_eq!(stderr, ["thread main panicked"]);
+        }
+        rsp => panic!("{rsp:?}"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn stderr_policies() {
+    let script = "echo a >&2; echo b >&2; echo c >&2; exit 3";
+    let stderr_at_death =
+        |client: crate::Client| match client.request::<lsp_types::request::Shutdown>(()) {
+            Err(crate::ClientError::ServerDied { stderr, .. }) => stderr,
+            rsp => panic!("{rsp:?}"),
+        };
+    let died = |stderr: StderrPolicy| {
+        let profile = crate::GenericProfile::new("sh", ["-c", script]).stderr_to(stderr);
+        stderr_at_death(crate::Client::spawn_profile(profile).unwrap())
+    };
+    assert_eq!(died(StderrPolicy::Capture(2)), ["b", "c"]);
+    assert!(died(StderrPolicy::Discard).is_empty());
+
+    // the same policies without a profile
+    let command = || {
+        let mut command = std::process::Command::new("sh");
+        command.args(["-c", script]);
+        command
+    };
+    let client = crate::Client::spawn_with_stderr(command(), StderrPolicy::Capture(1)).unwrap();
+    assert_eq!(stderr_at_death(client), ["c"]);
+    let no_restart = crate::RestartPolicy {
+        max_restarts: 0,
+        ..Default::default()
+    };
+    let client =
+        crate::Client::spawn_supervised(command, no_restart, StderrPolicy::Capture(1)).unwrap();
+    assert_eq!(stderr_at_death(client), ["c"]);
+
+    let dir = std::env::temp_dir().join(format!("lsp_client_stderr_{}", std::process::id()));
+    let log = dir.join("target").join("server.log");
+    assert!(died(StderrPolicy::File(log.clone())).is_empty());
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "a\nb\nc\n");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,303 @@
+/// What differs from one language server to the next: how to start it, what
+/// to send in `initialize`, how to tell it finished loading and its quirks.
+///
+/// [`RustAnalyzerProfile`] is the default of every client, [`GenericProfile`]
+/// covers servers such as clangd, gopls or pyright.
+pub trait ServerProfile: Send + Sync {
+    /// Used in messages, e.g. `rust-analyzer`.
+    fn name(&self) -> &str;
+
+    /// The command line and environment starting the server on stdio.
+    fn command(&self) -> crate::Result<std::process::Command>;
+
+    /// `initializationOptions` of `initialize`.
+    fn initialization_options(&self) -> Option<serde_json::Value> {
+        None
+    }
+
+    /// The answer to one `workspace/configuration` item, `null` meaning "use your defaults".
+    fn configuration(&self, _section: Option<&str>) -> serde_json::Value {
+        serde_json::Value::Null
+    }
+
+    /// The client capabilities declared in `initialize`, unless replaced by
+    /// [`Client::set_capabilities`](crate::Client::set_capabilities).
+    fn capabilities(&self) -> crate::Capabilities {
+        crate::Capabilities::default()
+    }
+
+    /// How [`Client::wait_ready`](crate::Client::wait_ready) tells the server
+    /// finished loading the workspace.
+    fn readiness(&self) -> ReadinessStrategy {
+        ReadinessStrategy::Immediate
+    }
+
+    fn quirks(&self) -> Quirks {
+        Quirks::default()
+    }
+
+    /// Where the server's stderr goes, `tracing` by default.
+    fn stderr(&self) -> crate::StderrPolicy {
+        crate::StderrPolicy::default()
+    }
+}
+
+/// How to tell a server finished loading the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadinessStrategy {
+    /// ready as soon as `initialized` was sent
+    Immediate,
+    /// ready once a work-done progress whose title starts with one of these
+    /// ended and none of them is active any more, e.g. `["indexing"]` for clangd
+    ProgressEnd(Vec<String>),
+    /// rust-analyzer's `experimental/serverStatus` turning quiescent, falling
+    /// back to the end of these loading progress titles when it is not sent
+    ServerStatus(Vec<String>),
+}
+
+/// Deviations from the LSP spec the client works around.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quirks {
+    /// the server may never answer `shutdown`, so `exit` is sent after a short wait
+    pub no_shutdown_response: bool,
+}
+
+/// rust-analyzer, located with [`RustAnalyzer::locate`](crate::RustAnalyzer::locate)
+/// and configured with a [`RaConfig`](crate::RaConfig).
+#[derive(Debug, Clone, PartialEq)]
+pub struct RustAnalyzerProfile {
+    binary: Option<std::path::PathBuf>,
+    env: Vec<(String, String)>,
+    ra_config: crate::RaConfig,
+    stderr: crate::StderrPolicy,
+}
+
+// titles of the progress rust-analyzer reports while loading a workspace, from
+// crates/rust-analyzer/src/reload.rs and main_loop.rs
+const RA_LOADING_TITLES: [&str; 5] = [
+    "Fetching",
+    "Loading",
+    "Roots Scanned",
+    "Indexing",
+    "Building",
+];
+
+impl Default for RustAnalyzerProfile {
+    /// The rust-analyzer found first, with `checkOnSave` disabled.
+    fn default() -> Self {
+        Self {
+            binary: None,
+            env: Vec::new(),
+            ra_config: crate::RaConfig::default().check_on_save(false),
+            stderr: crate::StderrPolicy::default(),
+        }
+    }
+}
+
+impl RustAnalyzerProfile {
+    /// Uses the binary at `path` instead of searching for one.
+    pub fn binary(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.binary = Some(path.into());
+        self
+    }
+
+    /// Sets an environment variable of the server, e.g. `RA_LOG`.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn ra_config(mut self, ra_config: crate::RaConfig) -> Self {
+        self.ra_config = ra_config;
+        self
+    }
+
+    /// Where `RA_LOG` output and panics go, e.g. `StderrPolicy::File("target/ra.log".into())`.
+    pub fn stderr_to(mut self, stderr: crate::StderrPolicy) -> Self {
+        self.stderr = stderr;
+        self
+    }
+}
+
+impl ServerProfile for RustAnalyzerProfile {
+    fn name(&self) -> &str {
+        "rust-analyzer"
+    }
+
+    fn command(&self) -> crate::Result<std::process::Command> {
+        let mut command = crate::RustAnalyzer::locate(self.binary.as_deref())?.command();
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        Ok(command)
+    }
+
+    // crates/rust-analyzer/src/bin/main.rs `fn run_server` config.update
+    fn initialization_options(&self) -> Option<serde_json::Value> {
+        Some(self.ra_config.to_json())
+    }
+
+    fn configuration(&self, section: Option<&str>) -> serde_json::Value {
+        self.ra_config.section(section)
+    }
+
+    fn readiness(&self) -> ReadinessStrategy {
+        ReadinessStrategy::ServerStatus(RA_LOADING_TITLES.map(String::from).to_vec())
+    }
+
+    /**
+    rust-analyzer has no ShutdownResponse
+    ```ignore
+    RequestDispatcher { req: Some(req), global_state: self }
+        .on_sync_mut::<lsp_types::request::Shutdown>(|s, ()| {
+            s.shutdown_requested = true;
+            Ok(())
+        })
+    ```
+    */
+    fn quirks(&self) -> Quirks {
+        Quirks {
+            no_shutdown_response: true,
+        }
+    }
+
+    fn stderr(&self) -> crate::StderrPolicy {
+        self.stderr.clone()
+    }
+}
+
+/// Any language server speaking LSP over stdio, e.g.
+/// `GenericProfile::new("clangd", ["--background-index"])`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericProfile {
+    program: String,
+    args: Vec<String>,
+    env: Vec<(String, String)>,
+    initialization_options: Option<serde_json::Value>,
+    /// the answer to every `workspace/configuration` item, keyed by section
+    configuration: serde_json::Map<String, serde_json::Value>,
+    readiness: ReadinessStrategy,
+    quirks: Quirks,
+    stderr: crate::StderrPolicy,
+}
+
+impl GenericProfile {
+    pub fn new<I, S>(program: impl Into<String>, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            program: program.into(),
+            args: args.into_iter().map(Into::into).collect(),
+            env: Vec::new(),
+            initialization_options: None,
+            configuration: serde_json::Map::new(),
+            readiness: ReadinessStrategy::Immediate,
+            quirks: Quirks::default(),
+            stderr: crate::StderrPolicy::default(),
+        }
+    }
+
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.push((key.into(), value.into()));
+        self
+    }
+
+    /// `initializationOptions` of `initialize`
+    pub fn init_options(mut self, options: serde_json::Value) -> Self {
+        self.initialization_options = Some(options);
+        self
+    }
+
+    /// Answers `workspace/configuration` items of `section`, e.g. `gopls`, with `value`.
+    pub fn section(mut self, section: impl Into<String>, value: serde_json::Value) -> Self {
+        self.configuration.insert(section.into(), value);
+        self
+    }
+
+    /// [`ReadinessStrategy::Immediate`] by default
+    pub fn ready_when(mut self, readiness: ReadinessStrategy) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    pub fn with_quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    pub fn stderr_to(mut self, stderr: crate::StderrPolicy) -> Self {
+        self.stderr = stderr;
+        self
+    }
+}
+
+impl ServerProfile for GenericProfile {
+    fn name(&self) -> &str {
+        &self.program
+    }
+
+    fn command(&self) -> crate::Result<std::process::Command> {
+        let mut command = std::process::Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.env.iter().map(|(key, value)| (key, value)));
+        Ok(command)
+    }
+
+    fn initialization_options(&self) -> Option<serde_json::Value> {
+        self.initialization_options.clone()
+    }
+
+    fn configuration(&self, section: Option<&str>) -> serde_json::Value {
+        section
+            .and_then(|section| self.configuration.get(section))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn capabilities(&self) -> crate::Capabilities {
+        // rust-analyzer's extension would only be noise to other servers
+        crate::Capabilities::default().set("experimental", serde_json::json!({}))
+    }
+
+    fn readiness(&self) -> ReadinessStrategy {
+        self.readiness.clone()
+    }
+
+    fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    fn stderr(&self) -> crate::StderrPolicy {
+        self.stderr.clone()
+    }
+}
+
+#[test]
+fn generic_profile() {
+    let profile = GenericProfile::new("gopls", ["serve"])
+        .env("GOFLAGS", "-mod=mod")
+        .section("gopls", serde_json::json!({ "staticcheck": true }))
+        .ready_when(ReadinessStrategy::ProgressEnd(vec!["Loading".to_string()]));
+    let command = profile.command().unwrap();
+    assert_eq!(command.get_program(), "gopls");
+    assert_eq!(command.get_args().collect::<Vec<_>>(), ["serve"]);
+    assert_eq!(
+        profile.configuration(Some("gopls")),
+        serde_json::json!({ "staticcheck": true })
+    );
+    assert_eq!(profile.configuration(None), serde_json::Value::Null);
+    assert_eq!(profile.initialization_options(), None);
+    assert_eq!(
+        profile.capabilities().to_json()["experimental"],
+        serde_json::json!({})
+    );
+    assert!(!profile.quirks().no_shutdown_response);
+
+    let rust_analyzer = RustAnalyzerProfile::default();
+    assert_eq!(
+        rust_analyzer.configuration(Some("rust-analyzer.checkOnSave.enable")),
+        serde_json::json!(false)
+    );
+    assert!(rust_analyzer.quirks().no_shutdown_response);
+}
@@ -0,0 +1,202 @@
+use lsp_types::notification::Notification;
+
+/// A work-done progress update from the server, passed to the callback of
+/// [`Client::wait_ready`](crate::Client::wait_ready).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    pub title: String,
+    pub message: Option<String>,
+    /// 0..=100 when the server reports it
+    pub percentage: Option<u32>,
+    /// the progress ended
+    pub done: bool,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum ProgressEvent {
+    Progress(Progress),
+    Ready,
+}
+
+impl ProgressEvent {
+    /// Hands a progress update to the `on_progress` of `wait_ready`, `true` once ready.
+    pub(crate) fn ready(self, on_progress: &mut impl FnMut(&Progress)) -> bool {
+        match self {
+            ProgressEvent::Progress(progress) => {
+                on_progress(&progress);
+                false
+            }
+            ProgressEvent::Ready => true,
+        }
+    }
+}
+
+/// Tracks `$/progress` and rust-analyzer's `experimental/serverStatus` to tell
+/// when the server finished loading the workspace.
+pub(crate) struct Readiness {
+    strategy: crate::ReadinessStrategy,
+    /// title of every progress token that began but did not end yet
+    active: std::collections::HashMap<lsp_types::ProgressToken, String>,
+    loading_ended: bool,
+    /// `None` until the server sends its first `experimental/serverStatus`
+    quiescent: Option<bool>,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self::new(crate::ReadinessStrategy::Immediate)
+    }
+}
+
+impl Readiness {
+    pub(crate) fn new(strategy: crate::ReadinessStrategy) -> Self {
+        Self {
+            strategy,
+            active: std::collections::HashMap::new(),
+            loading_ended: false,
+            quiescent: None,
+        }
+    }
+
+    /// Forgets everything seen from a previous server, keeping the strategy.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.strategy.clone());
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        match (&self.strategy, self.quiescent) {
+            (crate::ReadinessStrategy::Immediate, _) => true,
+            (crate::ReadinessStrategy::ServerStatus(_), Some(quiescent)) => quiescent,
+            // servers without serverStatus: every loading progress has ended
+            (crate::ReadinessStrategy::ServerStatus(_), None)
+            | (crate::ReadinessStrategy::ProgressEnd(_), _) => {
+                self.loading_ended && !self.active.values().any(|title| self.is_loading(title))
+            }
+        }
+    }
+
+    fn is_loading(&self, title: &str) -> bool {
+        match &self.strategy {
+            crate::ReadinessStrategy::Immediate => false,
+            crate::ReadinessStrategy::ProgressEnd(titles)
+            | crate::ReadinessStrategy::ServerStatus(titles) => {
+                titles.iter().any(|t| title.starts_with(t.as_str()))
+            }
+        }
+    }
+
+    /// Feeds a server notification, returning the events it produced.
+    pub(crate) fn update(&mut self, notif: &lsp_server::Notification) -> Vec<ProgressEvent> {
+        let was_ready = self.is_ready();
+        let mut events = Vec::new();
+        match notif.method.as_str() {
+            <lsp_types::notification::Progress as Notification>::METHOD => {
+                let params =
+                    match serde_json::from_value::<lsp_types::ProgressParams>(notif.params.clone())
+                    {
+                        Ok(params) => params,
+                        Err(_) => return events,
+                    };
+                let lsp_types::ProgressParamsValue::WorkDone(progress) = params.value;
+                events.push(ProgressEvent::Progress(self.track(params.token, progress)));
+            }
+            <crate::ra_ext::ServerStatusNotification as Notification>::METHOD => {
+                if let Ok(status) = serde_json::from_value::<crate::ra_ext::ServerStatusParams>(
+                    notif.params.clone(),
+                ) {
+                    self.quiescent = Some(status.quiescent);
+                }
+            }
+            _ => return events,
+        }
+        if !was_ready && self.is_ready() {
+            events.push(ProgressEvent::Ready);
+        }
+        events
+    }
+
+    fn track(
+        &mut self,
+        token: lsp_types::ProgressToken,
+        progress: lsp_types::WorkDoneProgress,
+    ) -> Progress {
+        match progress {
+            lsp_types::WorkDoneProgress::Begin(begin) => {
+                self.active.insert(token, begin.title.clone());
+                Progress {
+                    title: begin.title,
+                    message: begin.message,
+                    percentage: begin.percentage,
+                    done: false,
+                }
+            }
+            lsp_types::WorkDoneProgress::Report(report) => Progress {
+                title: self.active.get(&token).cloned().unwrap_or_default(),
+                message: report.message,
+                percentage: report.percentage,
+                done: false,
+            },
+            lsp_types::WorkDoneProgress::End(end) => {
+                let title = self.active.remove(&token).unwrap_or_default();
+                if self.is_loading(&title) {
+                    self.loading_ended = true;
+                }
+                Progress {
+                    title,
+                    message: end.message,
+                    percentage: None,
+                    done: true,
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn ready_after_loading_progress_or_quiescent() {
+    let progress = |token: &str, value: serde_json::Value| {
+        lsp_server::Notification::new(
+            "$/progress".to_string(),
+            serde_json::json!({ "token": token, "value": value }),
+        )
+    };
+    let mut readiness = Readiness::new(crate::ReadinessStrategy::ServerStatus(vec![
+        "Indexing".to_string()
+    ]));
+    readiness.update(&progress(
+        "rustAnalyzer/Indexing",
+        serde_json::json!({ "kind": "begin", "title": "Indexing", "percentage": 0 }),
+    ));
+    let events = readiness.update(&progress(
+        "rustAnalyzer/Indexing",
+        serde_json::json!({ "kind": "report", "message": "1/2 (core)", "percentage": 50 }),
+    ));
+    assert!(matches!(
+        &events[..],
+        [ProgressEvent::Progress(Progress {
+            percentage: Some(50),
+            done: false,
+            ..
+        })]
+    ));
+    assert!(!readiness.is_ready());
+    let events = readiness.update(&progress(
+        "rustAnalyzer/Indexing",
+        serde_json::json!({ "kind": "end" }),
+    ));
+    assert!(matches!(events.last(), Some(ProgressEvent::Ready)));
+
+    // serverStatus takes precedence once the server sends it
+    let status = |quiescent: bool| {
+        lsp_server::Notification::new(
+            "experimental/serverStatus".to_string(),
+            serde_json::json!({ "health": "ok", "quiescent": quiescent }),
+        )
+    };
+    readiness.update(&status(false));
+    assert!(!readiness.is_ready());
+    assert!(matches!(
+        &readiness.update(&status(true))[..],
+        [ProgressEvent::Ready]
+    ));
+}
@@ -0,0 +1,186 @@
+//! Message building and decoding shared by the blocking and async clients.
+
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+
+pub(crate) struct ReqId(std::sync::atomic::AtomicI32);
+
+impl ReqId {
+    pub(crate) fn new() -> Self {
+        Self(std::sync::atomic::AtomicI32::new(0))
+    }
+
+    pub(crate) fn inc(&self) -> lsp_server::RequestId {
+        let id = self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        lsp_server::RequestId::from(id)
+    }
+}
+
+/// How long [`Client::init`](crate::Client::init) waits for the workspace to load.
+pub(crate) const DEFAULT_READY_DEADLINE: std::time::Duration = std::time::Duration::from_secs(300);
+
+/// How long to wait for the `shutdown` response of a server with
+/// [`Quirks::no_shutdown_response`](crate::Quirks::no_shutdown_response).
+const SHUTDOWN_RESPONSE_WAIT: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// The `initialize` request, once `router` answers what the server may ask
+/// meanwhile: `workspace/configuration` with the settings of `profile` and
+/// `workspace/workspaceFolders` with the folders of `workspace`.
+pub(crate) fn initialize(
+    id: lsp_server::RequestId,
+    workspace: &crate::Workspace,
+    profile: &std::sync::Arc<dyn crate::ServerProfile>,
+    capabilities: Option<&crate::Capabilities>,
+    router: &mut crate::router::Router,
+) -> crate::Result<lsp_server::Request> {
+    let capabilities = capabilities
+        .cloned()
+        .unwrap_or_else(|| profile.capabilities());
+    let req = lsp_server::Request {
+        id,
+        method: <lsp_types::request::Initialize as Request>::METHOD.to_string(),
+        params: initialize_params(workspace, &**profile, &capabilities)?,
+    };
+    let config_profile = profile.clone();
+    router.on_request_fallback(
+        <lsp_types::request::WorkspaceConfiguration as Request>::METHOD.to_string(),
+        Box::new(move |params| configuration(&*config_profile, params)),
+    );
+    router.set_readiness(profile.readiness());
+    let folders = workspace.folders().to_vec();
+    router.on_request_fallback(
+        <lsp_types::request::WorkspaceFoldersRequest as Request>::METHOD.to_string(),
+        Box::new(move |_| Ok(serde_json::to_value(&folders).unwrap_or_default())),
+    );
+    Ok(req)
+}
+
+/// How long to wait for the `shutdown` response: `default`, but only a second
+/// for a server with [`Quirks::no_shutdown_response`](crate::Quirks::no_shutdown_response).
+pub(crate) fn shutdown_timeout(
+    profile: &dyn crate::ServerProfile,
+    default: Option<std::time::Duration>,
+) -> Option<std::time::Duration> {
+    if profile.quirks().no_shutdown_response {
+        Some(SHUTDOWN_RESPONSE_WAIT)
+    } else {
+        default
+    }
+}
+
+/// How `shutdown` went, given it was awaited for [`shutdown_timeout`]: a
+/// server known not to answer it timing out is fine.
+pub(crate) fn shutdown_result(
+    profile: &dyn crate::ServerProfile,
+    result: crate::Result<()>,
+) -> crate::Result<()> {
+    match result {
+        Err(crate::ClientError::Timeout) if profile.quirks().no_shutdown_response => Ok(()),
+        result => result,
+    }
+}
+
+fn initialize_params(
+    workspace: &crate::Workspace,
+    profile: &dyn crate::ServerProfile,
+    capabilities: &crate::Capabilities,
+) -> crate::Result<serde_json::Value> {
+    let mut params = serde_json::to_value(lsp_types::InitializeParams {
+        process_id: Some(std::process::id()),
+        root_uri: Some(workspace.root_uri().clone()),
+        workspace_folders: Some(workspace.folders().to_vec()),
+        initialization_options: profile.initialization_options(),
+        ..Default::default()
+    })?;
+    // lsp-types 0.93 lacks the LSP 3.17 capabilities, so they are sent as plain JSON
+    params["capabilities"] = capabilities.to_json();
+    Ok(params)
+}
+
+/// Answers `workspace/configuration` pulls with the same settings sent in `initialize`.
+fn configuration(
+    profile: &dyn crate::ServerProfile,
+    params: serde_json::Value,
+) -> Result<serde_json::Value, lsp_server::ResponseError> {
+    let params =
+        serde_json::from_value::<lsp_types::ConfigurationParams>(params).map_err(|err| {
+            lsp_server::ResponseError {
+                code: lsp_server::ErrorCode::InvalidParams as i32,
+                message: err.to_string(),
+                data: None,
+            }
+        })?;
+    Ok(serde_json::Value::Array(
+        params
+            .items
+            .iter()
+            .map(|item| profile.configuration(item.section.as_deref()))
+            .collect(),
+    ))
+}
+
+pub(crate) fn request<R: Request>(
+    id: lsp_server::RequestId,
+    params: R::Params,
+) -> crate::Result<lsp_server::Request> {
+    Ok(lsp_server::Request {
+        id,
+        method: R::METHOD.to_string(),
+        params: serde_json::to_value(params)?,
+    })
+}
+
+pub(crate) fn notification<N: Notification>(
+    params: N::Params,
+) -> crate::Result<lsp_server::Notification> {
+    Ok(lsp_server::Notification {
+        method: N::METHOD.to_string(),
+        params: serde_json::to_value(params)?,
+    })
+}
+
+pub(crate) fn cancel(id: &lsp_server::RequestId) -> crate::Result<lsp_server::Notification> {
+    // RequestId does not expose its repr, but serializes to the same number or string
+    let id = serde_json::from_value(serde_json::to_value(id)?)?;
+    notification::<lsp_types::notification::Cancel>(lsp_types::CancelParams { id })
+}
+
+/// Whether reading from the server failed only because the stream ended in
+/// the middle of a message, i.e. the server is gone like at a clean EOF.
+pub(crate) fn is_eof(err: &std::io::Error) -> bool {
+    err.kind() == std::io::ErrorKind::UnexpectedEof && !is_json(err)
+}
+
+/// Builds the error failing every request in flight once reading from the
+/// server failed with `err`, e.g. on a malformed frame or invalid JSON.
+pub(crate) fn read_error(err: std::io::Error) -> impl Fn() -> crate::ClientError + Send + 'static {
+    let json = is_json(&err);
+    let kind = err.kind();
+    let message = err.to_string();
+    move || {
+        if json {
+            crate::ClientError::Json(<serde_json::Error as serde::de::Error>::custom(&message))
+        } else {
+            crate::ClientError::Io(std::io::Error::new(kind, message.clone()))
+        }
+    }
+}
+
+fn is_json(err: &std::io::Error) -> bool {
+    err.get_ref()
+        .is_some_and(|inner| inner.is::<serde_json::Error>())
+}
+
+/// Turns an error response into [`ClientError::Rpc`](crate::ClientError::Rpc).
+pub(crate) fn result_value(rsp: lsp_server::Response) -> crate::Result<Option<serde_json::Value>> {
+    match rsp.error {
+        Some(err) => Err(err.into()),
+        None => Ok(rsp.result),
+    }
+}
+
+pub(crate) fn result<R: Request>(rsp: lsp_server::Response) -> crate::Result<R::Result> {
+    // `()` and `Option<_>` results may be sent as a missing `result`
+    let result = result_value(rsp)?.unwrap_or(serde_json::Value::Null);
+    Ok(serde_json::from_value(result)?)
+}
@@ -0,0 +1,229 @@
+/// rust-analyzer settings, sent as `initializationOptions` and in answer to
+/// `workspace/configuration` pulls.
+///
+/// `rust_analyzer::config::ConfigData` is private, so this builds the same
+/// JSON the VS Code extension sends, see crates/rust-analyzer/src/config.rs
+/// for the keys.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RaConfig {
+    json: serde_json::Map<String, serde_json::Value>,
+}
+
+/// `workspace.symbol.search.scope`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolSearchScope {
+    Workspace,
+    WorkspaceAndDependencies,
+}
+
+/// `workspace.symbol.search.kind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolSearchKind {
+    OnlyTypes,
+    AllSymbols,
+}
+
+impl RaConfig {
+    /// `checkOnSave.enable`, whether to run `cargo check` after every save
+    pub fn check_on_save(self, enable: bool) -> Self {
+        self.set("checkOnSave.enable", enable)
+    }
+
+    /// `cargo.features`, the features to activate
+    pub fn cargo_features<I, S>(self, features: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let features = features
+            .into_iter()
+            .map(Into::into)
+            .collect::<Vec<String>>();
+        self.set("cargo.features", features)
+    }
+
+    /// `cargo.allFeatures`, activate every feature. Newer rust-analyzer
+    /// releases migrate it to `cargo.features = "all"`.
+    pub fn cargo_all_features(self) -> Self {
+        self.set("cargo.allFeatures", true)
+    }
+
+    /// `cargo.target`, the target triple to analyze for
+    pub fn cargo_target(self, target: impl Into<String>) -> Self {
+        self.set("cargo.target", target.into())
+    }
+
+    /// `procMacro.enable`
+    pub fn proc_macro(self, enable: bool) -> Self {
+        self.set("procMacro.enable", enable)
+    }
+
+    /// adds to `cargo.cfgs` a `--cfg key` or `--cfg key="value"` set for every crate
+    pub fn cfg(mut self, key: &str, value: Option<&str>) -> Self {
+        let cfgs = entry(&mut self.json, "cargo.cfgs");
+        if !cfgs.is_object() {
+            *cfgs = serde_json::Value::Object(serde_json::Map::new());
+        }
+        if let serde_json::Value::Object(cfgs) = cfgs {
+            cfgs.insert(key.to_string(), value.into());
+        }
+        self
+    }
+
+    /// `workspace.symbol.search.limit`, the max number of `workspace/symbol` results
+    pub fn workspace_symbol_search_limit(self, limit: usize) -> Self {
+        self.set("workspace.symbol.search.limit", limit)
+    }
+
+    /// `workspace.symbol.search.scope`
+    pub fn workspace_symbol_search_scope(self, scope: SymbolSearchScope) -> Self {
+        let scope = match scope {
+            SymbolSearchScope::Workspace => "workspace",
+            SymbolSearchScope::WorkspaceAndDependencies => "workspace_and_dependencies",
+        };
+        self.set("workspace.symbol.search.scope", scope)
+    }
+
+    /// `workspace.symbol.search.kind`
+    pub fn workspace_symbol_search_kind(self, kind: SymbolSearchKind) -> Self {
+        let kind = match kind {
+            SymbolSearchKind::OnlyTypes => "only_types",
+            SymbolSearchKind::AllSymbols => "all_symbols",
+        };
+        self.set("workspace.symbol.search.kind", kind)
+    }
+
+    /// `files.excludeDirs`, directories rust-analyzer neither indexes nor watches
+    pub fn exclude_dirs<I, P>(self, dirs: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<std::path::Path>,
+    {
+        self.set("files.excludeDirs", paths(dirs))
+    }
+
+    /// `linkedProjects`, `Cargo.toml` or `rust-project.json` files to load
+    /// instead of discovering them from the workspace folders
+    pub fn linked_projects<I, P>(self, projects: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<std::path::Path>,
+    {
+        self.set("linkedProjects", paths(projects))
+    }
+
+    /// Sets any setting by its dotted key, e.g. `set("cargo.buildScripts.enable", false)`.
+    pub fn set(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        *entry(&mut self.json, key) = value.into();
+        self
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(self.json.clone())
+    }
+
+    /// The answer to a `workspace/configuration` item: the whole config for the
+    /// `rust-analyzer` section, the nested value for `rust-analyzer.some.key`.
+    pub fn section(&self, section: Option<&str>) -> serde_json::Value {
+        let key = match section {
+            Some("rust-analyzer") => return self.to_json(),
+            Some(section) => match section.strip_prefix("rust-analyzer.") {
+                Some(key) => key,
+                None => return serde_json::Value::Null,
+            },
+            None => return serde_json::Value::Null,
+        };
+        let mut value = &self.json;
+        let mut keys = key.split('.').peekable();
+        while let Some(key) = keys.next() {
+            match (value.get(key), keys.peek()) {
+                (Some(found), None) => return found.clone(),
+                (Some(serde_json::Value::Object(nested)), Some(_)) => value = nested,
+                _ => break,
+            }
+        }
+        serde_json::Value::Null
+    }
+}
+
+/// The value at dotted `key`, creating the nested objects on the way.
+pub(crate) fn entry<'a>(
+    json: &'a mut serde_json::Map<String, serde_json::Value>,
+    key: &str,
+) -> &'a mut serde_json::Value {
+    let (parent, last) = match key.rsplit_once('.') {
+        Some((parent, last)) => (Some(parent), last),
+        None => (None, key),
+    };
+    let mut object = json;
+    for key in parent.into_iter().flat_map(|parent| parent.split('.')) {
+        let nested = object
+            .entry(key.to_string())
+            .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+        if !nested.is_object() {
+            *nested = serde_json::Value::Object(serde_json::Map::new());
+        }
+        object = nested.as_object_mut().expect("replaced by an object above");
+    }
+    object
+        .entry(last.to_string())
+        .or_insert(serde_json::Value::Null)
+}
+
+fn paths<I, P>(paths: I) -> Vec<String>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<std::path::Path>,
+{
+    paths
+        .into_iter()
+        .map(|path| path.as_ref().to_string_lossy().into_owned())
+        .collect()
+}
+
+#[test]
+fn ra_config_json() {
+    let config = RaConfig::default()
+        .check_on_save(false)
+        .cargo_all_features()
+        .cargo_target("wasm32-unknown-unknown")
+        .proc_macro(true)
+        .cfg("feature", Some("mock"))
+        .cfg("miri", None)
+        .workspace_symbol_search_limit(1024)
+        .workspace_symbol_search_scope(SymbolSearchScope::WorkspaceAndDependencies)
+        .workspace_symbol_search_kind(SymbolSearchKind::AllSymbols)
+        .exclude_dirs(["target"])
+        .linked_projects(["crates/a/Cargo.toml"])
+        .set("cargo.buildScripts.enable", false);
+    assert_eq!(
+        config.to_json(),
+        serde_json::json!({
+            "checkOnSave": { "enable": false },
+            "cargo": {
+                "allFeatures": true,
+                "target": "wasm32-unknown-unknown",
+                "cfgs": { "feature": "mock", "miri": null },
+                "buildScripts": { "enable": false }
+            },
+            "procMacro": { "enable": true },
+            "workspace": {
+                "symbol": {
+                    "search": {
+                        "limit": 1024,
+                        "scope": "workspace_and_dependencies",
+                        "kind": "all_symbols"
+                    }
+                }
+            },
+            "files": { "excludeDirs": ["target"] },
+            "linkedProjects": ["crates/a/Cargo.toml"]
+        })
+    );
+    assert_eq!(config.section(Some("rust-analyzer")), config.to_json());
+    assert_eq!(
+        config.section(Some("rust-analyzer.cargo.target")),
+        serde_json::json!("wasm32-unknown-unknown")
+    );
+    assert_eq!(config.section(Some("editor")), serde_json::Value::Null);
+}
@@ -0,0 +1,524 @@
+//! rust-analyzer's LSP extensions, see docs/dev/lsp-extensions.md in the
+//! rust-analyzer repo. Mirrors crates/rust-analyzer/src/lsp_ext.rs at
+//! commit a670ff888437f4b6a3d24cc2996e9f969a87cbae, so the client does not
+//! have to depend on the whole analyzer for them.
+
+use lsp_types::notification::Notification;
+use lsp_types::request::Request;
+use lsp_types::{
+    PartialResultParams, Position, Range, TextDocumentIdentifier, TextDocumentPositionParams,
+    WorkDoneProgressParams,
+};
+use serde::{Deserialize, Serialize};
+
+pub enum AnalyzerStatus {}
+
+impl Request for AnalyzerStatus {
+    type Params = AnalyzerStatusParams;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/analyzerStatus";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerStatusParams {
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+pub enum MemoryUsage {}
+
+impl Request for MemoryUsage {
+    type Params = ();
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/memoryUsage";
+}
+
+pub enum ReloadWorkspace {}
+
+impl Request for ReloadWorkspace {
+    type Params = ();
+    type Result = ();
+    const METHOD: &'static str = "rust-analyzer/reloadWorkspace";
+}
+
+pub enum SyntaxTree {}
+
+impl Request for SyntaxTree {
+    type Params = SyntaxTreeParams;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/syntaxTree";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxTreeParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Option<Range>,
+}
+
+pub enum ViewHir {}
+
+impl Request for ViewHir {
+    type Params = TextDocumentPositionParams;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/viewHir";
+}
+
+pub enum ViewFileText {}
+
+impl Request for ViewFileText {
+    type Params = TextDocumentIdentifier;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/viewFileText";
+}
+
+pub enum ViewCrateGraph {}
+
+impl Request for ViewCrateGraph {
+    type Params = ViewCrateGraphParams;
+    /// the graph in graphviz dot format
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/viewCrateGraph";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewCrateGraphParams {
+    /// include the crates.io dependencies and the sysroot crates
+    pub full: bool,
+}
+
+pub enum ViewItemTree {}
+
+impl Request for ViewItemTree {
+    type Params = ViewItemTreeParams;
+    type Result = String;
+    const METHOD: &'static str = "rust-analyzer/viewItemTree";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewItemTreeParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+pub enum ExpandMacro {}
+
+impl Request for ExpandMacro {
+    type Params = ExpandMacroParams;
+    type Result = Option<ExpandedMacro>;
+    const METHOD: &'static str = "rust-analyzer/expandMacro";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandMacroParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandedMacro {
+    pub name: String,
+    pub expansion: String,
+}
+
+pub enum MatchingBrace {}
+
+impl Request for MatchingBrace {
+    type Params = MatchingBraceParams;
+    type Result = Vec<Position>;
+    const METHOD: &'static str = "experimental/matchingBrace";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchingBraceParams {
+    pub text_document: TextDocumentIdentifier,
+    pub positions: Vec<Position>,
+}
+
+pub enum ParentModule {}
+
+impl Request for ParentModule {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<lsp_types::GotoDefinitionResponse>;
+    const METHOD: &'static str = "experimental/parentModule";
+}
+
+pub enum JoinLines {}
+
+impl Request for JoinLines {
+    type Params = JoinLinesParams;
+    type Result = Vec<lsp_types::TextEdit>;
+    const METHOD: &'static str = "experimental/joinLines";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinLinesParams {
+    pub text_document: TextDocumentIdentifier,
+    pub ranges: Vec<Range>,
+}
+
+pub enum OnEnter {}
+
+impl Request for OnEnter {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<Vec<SnippetTextEdit>>;
+    const METHOD: &'static str = "experimental/onEnter";
+}
+
+/// A `TextEdit` whose `new_text` may be a snippet, `$0` marking the cursor.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetTextEdit {
+    pub range: Range,
+    pub new_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<lsp_types::InsertTextFormat>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub annotation_id: Option<lsp_types::ChangeAnnotationIdentifier>,
+}
+
+pub enum MoveItem {}
+
+impl Request for MoveItem {
+    type Params = MoveItemParams;
+    type Result = Vec<SnippetTextEdit>;
+    const METHOD: &'static str = "experimental/moveItem";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveItemParams {
+    pub direction: MoveItemDirection,
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveItemDirection {
+    Up,
+    Down,
+}
+
+pub enum Runnables {}
+
+impl Request for Runnables {
+    type Params = RunnablesParams;
+    type Result = Vec<Runnable>;
+    const METHOD: &'static str = "experimental/runnables";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnablesParams {
+    pub text_document: TextDocumentIdentifier,
+    /// only the runnables at this position, all of the file's when `None`
+    pub position: Option<Position>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Runnable {
+    pub label: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub location: Option<lsp_types::LocationLink>,
+    pub kind: RunnableKind,
+    pub args: CargoRunnable,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RunnableKind {
+    Cargo,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CargoRunnable {
+    /// command to be executed instead of cargo
+    pub override_cargo: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace_root: Option<std::path::PathBuf>,
+    /// e.g. `["test", "--package", "foo"]`
+    pub cargo_args: Vec<String>,
+    /// from the `cargo.extraArgs` setting
+    pub cargo_extra_args: Vec<String>,
+    /// after `--`, e.g. the test name
+    pub executable_args: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expect_test: Option<bool>,
+}
+
+pub enum RelatedTests {}
+
+impl Request for RelatedTests {
+    type Params = TextDocumentPositionParams;
+    type Result = Vec<TestInfo>;
+    const METHOD: &'static str = "rust-analyzer/relatedTests";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TestInfo {
+    pub runnable: Runnable,
+}
+
+pub enum Ssr {}
+
+impl Request for Ssr {
+    type Params = SsrParams;
+    type Result = lsp_types::WorkspaceEdit;
+    const METHOD: &'static str = "experimental/ssr";
+}
+
+/// Structural search and replace, e.g. `foo($a, $b) ==>> bar($b, $a)`.
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SsrParams {
+    pub query: String,
+    /// only check the query parses, the result is an empty edit
+    pub parse_only: bool,
+    /// where paths in the query are resolved
+    #[serde(flatten)]
+    pub position: TextDocumentPositionParams,
+    /// limit the replacement to these ranges of the document, everywhere when empty
+    pub selections: Vec<Range>,
+}
+
+pub enum OpenCargoToml {}
+
+impl Request for OpenCargoToml {
+    type Params = OpenCargoTomlParams;
+    type Result = Option<lsp_types::GotoDefinitionResponse>;
+    const METHOD: &'static str = "experimental/openCargoToml";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenCargoTomlParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+pub enum ExternalDocs {}
+
+impl Request for ExternalDocs {
+    type Params = TextDocumentPositionParams;
+    type Result = Option<lsp_types::Url>;
+    const METHOD: &'static str = "experimental/externalDocs";
+}
+
+/// `workspace/symbol` with rust-analyzer's `searchScope` and `searchKind`.
+pub enum WorkspaceSymbol {}
+
+impl Request for WorkspaceSymbol {
+    type Params = WorkspaceSymbolParams;
+    type Result = Option<Vec<lsp_types::SymbolInformation>>;
+    const METHOD: &'static str = "workspace/symbol";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSymbolParams {
+    #[serde(flatten)]
+    pub partial_result_params: PartialResultParams,
+    #[serde(flatten)]
+    pub work_done_progress_params: WorkDoneProgressParams,
+    /// A non-empty query string
+    pub query: String,
+    /// overrides the `workspace.symbol.search.scope` setting
+    pub search_scope: Option<WorkspaceSymbolSearchScope>,
+    /// overrides the `workspace.symbol.search.kind` setting
+    pub search_kind: Option<WorkspaceSymbolSearchKind>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkspaceSymbolSearchScope {
+    Workspace,
+    WorkspaceAndDependencies,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkspaceSymbolSearchKind {
+    OnlyTypes,
+    AllSymbols,
+}
+
+/// Sent when the client declares the `serverStatusNotification` experimental capability.
+pub enum ServerStatusNotification {}
+
+impl Notification for ServerStatusNotification {
+    type Params = ServerStatusParams;
+    const METHOD: &'static str = "experimental/serverStatus";
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct ServerStatusParams {
+    pub health: Health,
+    /// the server finished loading the workspace and has no pending work
+    pub quiescent: bool,
+    pub message: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Health {
+    Ok,
+    Warning,
+    Error,
+}
+
+pub enum CancelFlycheck {}
+
+impl Notification for CancelFlycheck {
+    type Params = ();
+    const METHOD: &'static str = "rust-analyzer/cancelFlycheck";
+}
+
+#[cfg(test)]
+fn round_trip<T>(value: T, json: serde_json::Value)
+where
+    T: Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+{
+    assert_eq!(serde_json::to_value(&value).unwrap(), json);
+    assert_eq!(serde_json::from_value::<T>(json).unwrap(), value);
+}
+
+#[test]
+fn ra_ext_serde_round_trip() {
+    let uri = lsp_types::Url::parse("file:///src/lib.rs").unwrap();
+    let text_document = TextDocumentIdentifier::new(uri.clone());
+    let position = Position::new(1, 4);
+    let range = Range::new(position, Position::new(1, 8));
+    let document_position = TextDocumentPositionParams::new(text_document.clone(), position);
+
+    round_trip(
+        AnalyzerStatusParams {
+            text_document: None,
+        },
+        serde_json::json!({ "textDocument": null }),
+    );
+    round_trip(
+        WorkspaceSymbolParams {
+            query: "unused".to_string(),
+            search_scope: Some(WorkspaceSymbolSearchScope::WorkspaceAndDependencies),
+            search_kind: Some(WorkspaceSymbolSearchKind::AllSymbols),
+            ..Default::default()
+        },
+        serde_json::json!({
+            "query": "unused",
+            "searchScope": "workspaceAndDependencies",
+            "searchKind": "allSymbols"
+        }),
+    );
+    round_trip(
+        ExpandMacroParams {
+            text_document: text_document.clone(),
+            position,
+        },
+        serde_json::json!({
+            "textDocument": { "uri": uri },
+            "position": { "line": 1, "character": 4 }
+        }),
+    );
+    round_trip(
+        Some(ExpandedMacro {
+            name: "vec".to_string(),
+            expansion: "<[_]>::into_vec(box [1])".to_string(),
+        }),
+        serde_json::json!({ "name": "vec", "expansion": "<[_]>::into_vec(box [1])" }),
+    );
+    round_trip(
+        SsrParams {
+            query: "foo($a) ==>> bar($a)".to_string(),
+            parse_only: false,
+            position: document_position.clone(),
+            selections: vec![range],
+        },
+        serde_json::json!({
+            "query": "foo($a) ==>> bar($a)",
+            "parseOnly": false,
+            "textDocument": { "uri": uri },
+            "position": { "line": 1, "character": 4 },
+            "selections": [{
+                "start": { "line": 1, "character": 4 },
+                "end": { "line": 1, "character": 8 }
+            }]
+        }),
+    );
+    let runnable = Runnable {
+        label: "test tests::it_works".to_string(),
+        location: None,
+        kind: RunnableKind::Cargo,
+        args: CargoRunnable {
+            override_cargo: None,
+            workspace_root: Some("/src".into()),
+            cargo_args: vec!["test".to_string(), "--lib".to_string()],
+            cargo_extra_args: Vec::new(),
+            executable_args: vec!["tests::it_works".to_string(), "--exact".to_string()],
+            expect_test: None,
+        },
+    };
+    let runnable_json = serde_json::json!({
+        "label": "test tests::it_works",
+        "kind": "cargo",
+        "args": {
+            "overrideCargo": null,
+            "workspaceRoot": "/src",
+            "cargoArgs": ["test", "--lib"],
+            "cargoExtraArgs": [],
+            "executableArgs": ["tests::it_works", "--exact"]
+        }
+    });
+    round_trip(
+        vec![runnable.clone()],
+        serde_json::Value::Array(vec![runnable_json.clone()]),
+    );
+    round_trip(
+        vec![TestInfo { runnable }],
+        serde_json::json!([{ "runnable": runnable_json }]),
+    );
+    round_trip(
+        RunnablesParams {
+            text_document,
+            position: None,
+        },
+        serde_json::json!({ "textDocument": { "uri": uri }, "position": null }),
+    );
+    round_trip(
+        ViewCrateGraphParams { full: true },
+        serde_json::json!({ "full": true }),
+    );
+    round_trip(
+        ServerStatusParams {
+            health: Health::Warning,
+            quiescent: true,
+            message: Some("failed to load workspace".to_string()),
+        },
+        serde_json::json!({
+            "health": "warning",
+            "quiescent": true,
+            "message": "failed to load workspace"
+        }),
+    );
+    round_trip(
+        MoveItemParams {
+            direction: MoveItemDirection::Up,
+            text_document: TextDocumentIdentifier::new(uri.clone()),
+            range,
+        },
+        serde_json::json!({
+            "direction": "Up",
+            "textDocument": { "uri": uri },
+            "range": {
+                "start": { "line": 1, "character": 4 },
+                "end": { "line": 1, "character": 8 }
+            }
+        }),
+    );
+}
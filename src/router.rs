@@ -0,0 +1,296 @@
+use lsp_types::request::Request;
+
+type RequestHandler = Box<
+    dyn FnMut(serde_json::Value) -> Result<serde_json::Value, lsp_server::ResponseError> + Send,
+>;
+
+pub(crate) type ResponseCallback = Box<dyn FnOnce(crate::Result<lsp_server::Response>) + Send>;
+/// returns `false` once the subscriber is gone, which unsubscribes it
+type NotificationCallback = Box<dyn FnMut(&lsp_server::Notification) -> bool + Send>;
+/// builds the error for requests made after the server is gone
+type ExitError = Box<dyn Fn() -> crate::ClientError + Send>;
+type ProgressCallback = Box<dyn FnMut(&crate::progress::ProgressEvent) -> bool + Send>;
+
+/// Demultiplexes messages read from the server: responses are matched to the
+/// request waiting on their id, notifications are fanned out to subscribers and
+/// server-to-client requests are answered by the registered handlers.
+#[derive(Default)]
+pub(crate) struct Router {
+    /// in-flight requests, completed when the response with their id arrives,
+    /// with the request itself when it is sent again after a server restart
+    pending: std::collections::HashMap<
+        lsp_server::RequestId,
+        (ResponseCallback, Option<lsp_server::Request>),
+    >,
+    /// `None` subscribes to every method
+    subscribers: Vec<(Option<String>, NotificationCallback)>,
+    handlers: std::collections::HashMap<String, RequestHandler>,
+    /// the answers `initialize` derives from the profile and workspace, used
+    /// for the methods the caller registered no handler for
+    fallback_handlers: std::collections::HashMap<String, RequestHandler>,
+    readiness: crate::progress::Readiness,
+    progress_watchers: Vec<ProgressCallback>,
+    /// set once the server closed its stdout, later requests fail immediately
+    closed: Option<ExitError>,
+}
+
+impl Router {
+    pub(crate) fn register(
+        &mut self,
+        id: lsp_server::RequestId,
+        on_response: ResponseCallback,
+        retry: Option<lsp_server::Request>,
+    ) -> crate::Result<()> {
+        if let Some(exited) = &self.closed {
+            return Err(exited());
+        }
+        self.pending.insert(id, (on_response, retry));
+        Ok(())
+    }
+
+    /// Forgets an in-flight request, completed with `reason` such as
+    /// [`ClientError::Timeout`](crate::ClientError::Timeout), its response will be
+    /// dropped when it arrives. Returns the `$/cancelRequest` to send, `None` when
+    /// the server already answered and there is nothing left to cancel.
+    pub(crate) fn cancel(
+        &mut self,
+        id: &lsp_server::RequestId,
+        reason: crate::ClientError,
+    ) -> crate::Result<Option<lsp_server::Notification>> {
+        match self.pending.remove(id) {
+            Some((on_response, _)) => {
+                on_response(Err(reason));
+                Ok(Some(crate::protocol::cancel(id)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `true` when the server is already ready, otherwise registers
+    /// `on_progress` for every later progress event.
+    pub(crate) fn watch_progress(&mut self, on_progress: ProgressCallback) -> crate::Result<bool> {
+        if let Some(exited) = &self.closed {
+            return Err(exited());
+        }
+        if self.readiness.is_ready() {
+            return Ok(true);
+        }
+        self.progress_watchers.push(on_progress);
+        Ok(false)
+    }
+
+    /// Fails every in-flight request with the error `exited` builds once the server is gone.
+    pub(crate) fn close<F>(&mut self, exited: F)
+    where
+        F: Fn() -> crate::ClientError + Send + 'static,
+    {
+        // dropping the callbacks disconnects everyone still waiting for progress or notifications
+        self.progress_watchers.clear();
+        self.subscribers.clear();
+        for (_, (on_response, _)) in self.pending.drain() {
+            on_response(Err(exited()));
+        }
+        self.closed = Some(Box::new(exited));
+    }
+
+    /// Forgets the dead server's state. In-flight requests registered for retry
+    /// are returned to be sent to the new server, the others fail with `died`.
+    pub(crate) fn restart<F>(&mut self, retry: bool, died: F) -> Vec<lsp_server::Request>
+    where
+        F: Fn() -> crate::ClientError,
+    {
+        self.readiness.reset();
+        let mut resend = Vec::new();
+        for (id, (on_response, request)) in std::mem::take(&mut self.pending) {
+            match request {
+                Some(request) if retry => {
+                    resend.push(request.clone());
+                    self.pending.insert(id, (on_response, Some(request)));
+                }
+                _ => on_response(Err(died())),
+            }
+        }
+        resend
+    }
+
+    pub(crate) fn set_readiness(&mut self, strategy: crate::ReadinessStrategy) {
+        self.readiness = crate::progress::Readiness::new(strategy);
+    }
+
+    pub(crate) fn subscribe(
+        &mut self,
+        method: Option<String>,
+        on_notification: NotificationCallback,
+    ) {
+        self.subscribers.push((method, on_notification));
+    }
+
+    pub(crate) fn on_request(&mut self, method: String, handler: RequestHandler) {
+        self.handlers.insert(method, handler);
+    }
+
+    /// Like [`Router::on_request`], but a handler registered with it wins
+    /// whether it was registered before or after.
+    pub(crate) fn on_request_fallback(&mut self, method: String, handler: RequestHandler) {
+        self.fallback_handlers.insert(method, handler);
+    }
+
+    /// [`Router::dispatch`] for the reader: a response nobody waits for is
+    /// dropped, the request it answers was already given up.
+    pub(crate) fn route(&mut self, msg: lsp_server::Message) -> Option<lsp_server::Message> {
+        self.dispatch(msg).ok().flatten()
+    }
+
+    /// Routes one incoming message, returning the reply to write back when the
+    /// message was a server-to-client request.
+    pub(crate) fn dispatch(
+        &mut self,
+        msg: lsp_server::Message,
+    ) -> crate::Result<Option<lsp_server::Message>> {
+        match msg {
+            lsp_server::Message::Response(rsp) => match self.pending.remove(&rsp.id) {
+                Some((on_response, _)) => {
+                    on_response(Ok(rsp));
+                    Ok(None)
+                }
+                None => Err(crate::ClientError::UnexpectedMessage(Box::new(
+                    lsp_server::Message::Response(rsp),
+                ))),
+            },
+            lsp_server::Message::Notification(notif) => {
+                for event in self.readiness.update(&notif) {
+                    self.progress_watchers
+                        .retain_mut(|on_progress| on_progress(&event));
+                }
+                self.subscribers
+                    .retain_mut(|(method, on_notification)| match method {
+                        Some(method) if *method != notif.method => true,
+                        _ => on_notification(&notif),
+                    });
+                Ok(None)
+            }
+            lsp_server::Message::Request(req) => {
+                let handler = match self.handlers.get_mut(&req.method) {
+                    Some(handler) => Some(handler),
+                    None => self.fallback_handlers.get_mut(&req.method),
+                };
+                let result = match handler {
+                    Some(handler) => handler(req.params),
+                    None => default_reply(&req),
+                };
+                let rsp = match result {
+                    Ok(result) => lsp_server::Response {
+                        id: req.id,
+                        result: Some(result),
+                        error: None,
+                    },
+                    Err(err) => lsp_server::Response {
+                        id: req.id,
+                        result: None,
+                        error: Some(err),
+                    },
+                };
+                Ok(Some(lsp_server::Message::Response(rsp)))
+            }
+        }
+    }
+}
+
+/// Replies for the server requests rust-analyzer sends to every client,
+/// so servers never block waiting on a client that registered no handler.
+fn default_reply(
+    req: &lsp_server::Request,
+) -> Result<serde_json::Value, lsp_server::ResponseError> {
+    match req.method.as_str() {
+        <lsp_types::request::WorkDoneProgressCreate as Request>::METHOD
+        | <lsp_types::request::RegisterCapability as Request>::METHOD
+        | <lsp_types::request::UnregisterCapability as Request>::METHOD
+        | <lsp_types::request::SemanticTokensRefresh as Request>::METHOD
+        | <lsp_types::request::CodeLensRefresh as Request>::METHOD
+        | <lsp_types::request::ShowMessageRequest as Request>::METHOD
+        | <lsp_types::request::WorkspaceFoldersRequest as Request>::METHOD
+        | "workspace/inlayHint/refresh"
+        | "workspace/diagnostic/refresh" => Ok(serde_json::Value::Null),
+        <lsp_types::request::WorkspaceConfiguration as Request>::METHOD => {
+            // one `null` per requested item means "use your defaults"
+            let items =
+                serde_json::from_value::<lsp_types::ConfigurationParams>(req.params.clone())
+                    .map(|params| params.items.len())
+                    .unwrap_or_default();
+            Ok(serde_json::Value::Array(vec![
+                serde_json::Value::Null;
+                items
+            ]))
+        }
+        <lsp_types::request::ApplyWorkspaceEdit as Request>::METHOD => {
+            Ok(serde_json::to_value(lsp_types::ApplyWorkspaceEditResponse {
+                applied: false,
+                failure_reason: Some("lsp_client does not apply workspace edits".to_string()),
+                failed_change: None,
+            })
+            .unwrap_or_default())
+        }
+        _ => Err(lsp_server::ResponseError {
+            code: lsp_server::ErrorCode::MethodNotFound as i32,
+            message: format!("unhandled server request {}", req.method),
+            data: None,
+        }),
+    }
+}
+
+#[test]
+fn route_interleaved_messages() {
+    let mut router = Router::default();
+    let id = lsp_server::RequestId::from(1);
+    let (tx, rx) = std::sync::mpsc::channel();
+    router
+        .register(id.clone(), Box::new(move |rsp| tx.send(rsp).unwrap()), None)
+        .unwrap();
+    let (diagnostics_tx, diagnostics) = std::sync::mpsc::channel();
+    router.subscribe(
+        Some("textDocument/publishDiagnostics".to_string()),
+        Box::new(move |notif| diagnostics_tx.send(notif.clone()).is_ok()),
+    );
+
+    let config_req = lsp_server::Request {
+        id: lsp_server::RequestId::from(0),
+        method: <lsp_types::request::WorkspaceConfiguration as Request>::METHOD.to_string(),
+        params: serde_json::json!({ "items": [{ "section": "rust-analyzer" }] }),
+    };
+    match router.dispatch(config_req.clone().into()).unwrap() {
+        Some(lsp_server::Message::Response(rsp)) => {
+            assert_eq!(rsp.result, Some(serde_json::json!([null])))
+        }
+        reply => panic!("{reply:?}"),
+    }
+    // a handler of the caller wins over a fallback, whatever the order
+    let answer =
+        |value: &'static str| -> RequestHandler { Box::new(move |_| Ok(serde_json::json!(value))) };
+    router.on_request_fallback(config_req.method.clone(), answer("fallback"));
+    router.on_request(config_req.method.clone(), answer("caller"));
+    router.on_request_fallback(config_req.method.clone(), answer("fallback"));
+    match router.dispatch(config_req.into()).unwrap() {
+        Some(lsp_server::Message::Response(rsp)) => {
+            assert_eq!(rsp.result, Some(serde_json::json!("caller")))
+        }
+        reply => panic!("{reply:?}"),
+    }
+    for method in ["$/progress", "textDocument/publishDiagnostics"] {
+        let notif = lsp_server::Notification::new(method.to_string(), serde_json::Value::Null);
+        assert!(router.dispatch(notif.into()).unwrap().is_none());
+    }
+    assert_eq!(diagnostics.try_iter().count(), 1);
+
+    assert!(rx.try_recv().is_err());
+    let rsp = lsp_server::Response::new_ok(id.clone(), ());
+    router.dispatch(rsp.clone().into()).unwrap();
+    assert!(rx.try_recv().unwrap().is_ok());
+    assert!(matches!(
+        router.dispatch(rsp.into()),
+        Err(crate::ClientError::UnexpectedMessage(_))
+    ));
+
+    router.close(|| crate::ClientError::ServerExited);
+    let err = router.register(id, Box::new(|_| {}), None).unwrap_err();
+    assert!(matches!(err, crate::ClientError::ServerExited));
+}
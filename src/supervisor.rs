@@ -0,0 +1,34 @@
+/// What a client started by [`Client::spawn_supervised`](crate::Client::spawn_supervised)
+/// does when the server crashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// after this many restarts the next crash is final and every request
+    /// fails with [`ClientError::ServerDied`](crate::ClientError::ServerDied)
+    pub max_restarts: u32,
+    /// send the requests in flight at crash time again to the new server,
+    /// instead of failing them with `ServerDied`
+    pub retry_in_flight: bool,
+    /// how long a new server may take to answer the replayed `initialize`
+    /// before it is killed, which counts as another crash
+    pub handshake_timeout: std::time::Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: 3,
+            retry_in_flight: false,
+            handshake_timeout: std::time::Duration::from_secs(30),
+        }
+    }
+}
+
+/// Everything needed to bring a crashed server back to where it was.
+pub(crate) struct Supervisor {
+    pub(crate) command: Box<dyn FnMut() -> std::process::Command + Send>,
+    pub(crate) stderr: crate::StderrPolicy,
+    pub(crate) policy: RestartPolicy,
+    pub(crate) restarts: u32,
+    /// the params of the last `initialize`, replayed to the new server
+    pub(crate) initialize_params: Option<serde_json::Value>,
+}
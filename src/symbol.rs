@@ -0,0 +1,288 @@
+//! Where the identifier of a symbol is: `workspace/symbol` locations span the
+//! whole item, attributes, doc comments and modifiers included, while
+//! `textDocument/references` and friends must be asked on the name itself.
+
+/// Keywords introducing the name of the item they declare.
+const DECLARING: [&str; 11] = [
+    "fn",
+    "struct",
+    "enum",
+    "union",
+    "trait",
+    "type",
+    "const",
+    "static",
+    "mod",
+    "macro_rules",
+    "let",
+];
+
+/// The `selectionRange` of the innermost symbol called `name` whose name lies
+/// in `range`, searched through nested `textDocument/documentSymbol` results.
+pub(crate) fn selection_range(
+    symbols: &[lsp_types::DocumentSymbol],
+    name: &str,
+    range: lsp_types::Range,
+) -> Option<lsp_types::Range> {
+    let contains = |position: lsp_types::Position| range.start <= position && position <= range.end;
+    symbols.iter().find_map(|symbol| {
+        let children = symbol.children.as_deref().unwrap_or_default();
+        selection_range(children, name, range).or_else(|| {
+            (symbol.name == name && contains(symbol.selection_range.start))
+                .then_some(symbol.selection_range)
+        })
+    })
+}
+
+/// The byte offset in Rust source `text` of identifier `name`, preferably right
+/// after a declaring keyword such as `fn`, never inside a comment, a literal or
+/// an attribute.
+pub(crate) fn scan_name(text: &str, name: &str) -> Option<usize> {
+    let mut first = None;
+    let mut previous_ident = "";
+    // inside `#[...]` while non-zero
+    let mut attr_depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        let rest = &text[i..];
+        let ch = rest.chars().next()?;
+        if rest.starts_with("//") {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            i += block_comment_len(rest);
+        } else if let Some(len) = string_len(rest) {
+            i += len;
+            previous_ident = "";
+        } else if ch == '\'' {
+            i += quote_len(rest);
+            previous_ident = "";
+        } else if rest.starts_with("#[") || rest.starts_with("#![") {
+            i += rest.find('[')? + 1;
+            attr_depth += 1;
+        } else if attr_depth > 0 && ch == '[' {
+            attr_depth += 1;
+            i += 1;
+        } else if attr_depth > 0 && ch == ']' {
+            attr_depth -= 1;
+            i += 1;
+        } else if ch == '_' || ch.is_alphabetic() {
+            let raw = rest.starts_with("r#") as usize * 2;
+            let len = raw + ident_len(&rest[raw..]);
+            let ident = &rest[raw..len];
+            if attr_depth == 0 && ident == name {
+                if DECLARING.contains(&previous_ident) {
+                    return Some(i + raw);
+                }
+                first.get_or_insert(i + raw);
+            }
+            previous_ident = ident;
+            i += len;
+        } else {
+            // `macro_rules! name`
+            if !ch.is_whitespace() && ch != '!' {
+                previous_ident = "";
+            }
+            i += ch.len_utf8();
+        }
+    }
+    first
+}
+
+fn ident_len(text: &str) -> usize {
+    text.find(|ch: char| ch != '_' && !ch.is_alphanumeric())
+        .unwrap_or(text.len())
+}
+
+/// The length of the (nested) block comment `text` starts with.
+fn block_comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    text.len()
+}
+
+/// The length of the string literal `text` starts with, raw and byte strings
+/// included, `None` if it does not start with one.
+fn string_len(text: &str) -> Option<usize> {
+    let prefix = text.find(|ch: char| ch != 'b' && ch != 'c' && ch != 'r')?;
+    let (prefix, body) = text.split_at(prefix);
+    if prefix.len() > 2 || prefix.matches('r').count() > 1 {
+        return None;
+    }
+    if prefix.ends_with('r') {
+        let hashes = body.find(|ch: char| ch != '#')?;
+        if !body[hashes..].starts_with('"') {
+            return None;
+        }
+        let close = format!("\"{}", "#".repeat(hashes));
+        let end = body[hashes + 1..]
+            .find(&close)
+            .map_or(body.len(), |end| hashes + 1 + end + close.len());
+        return Some(prefix.len() + end);
+    }
+    if !body.starts_with('"') {
+        return None;
+    }
+    let mut escaped = false;
+    for (i, ch) in body.char_indices().skip(1) {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' => return Some(prefix.len() + i + 1),
+            _ => {}
+        }
+    }
+    Some(text.len())
+}
+
+/// The length of the char literal or lifetime `text` starts with.
+fn quote_len(text: &str) -> usize {
+    let body = &text[1..];
+    if body.starts_with('\\') {
+        // an escaped char, up to its closing quote, in invalid code maybe not ASCII
+        let start = body.char_indices().nth(2).map_or(body.len(), |(i, _)| i);
+        return body[start..]
+            .find('\'')
+            .map_or(text.len(), |end| 1 + start + end + 1);
+    }
+    let mut chars = body.chars();
+    match (chars.next(), chars.next()) {
+        (Some(ch), Some('\'')) => 1 + ch.len_utf8() + 1,
+        // a lifetime or a label, its name is no identifier
+        _ => 1 + ident_len(body),
+    }
+}
+
+#[test]
+fn scan_names() {
+    let text = r##"
+/// Calls `used` once, see [`used`].
+#[doc = "used"]
+#[cfg_attr(feature = "x", path = "used.rs")]
+pub(crate) const unsafe fn used<'used>(_: &'used str) -> char {
+    /* used /* nested */ used */
+    let _ = r#"used"#;
+    'used'
+}
+"##;
+    let offset = scan_name(text, "used").unwrap();
+    assert!(text[..offset].ends_with("unsafe fn "), "{offset}");
+    assert_eq!(&text[offset..offset + 4], "used");
+
+    let text = "async fn r#match() {}\nmacro_rules! tested { () => {} }";
+    assert!(text[..scan_name(text, "match").unwrap()].ends_with("fn r#"));
+    assert!(text[..scan_name(text, "tested").unwrap()].ends_with("macro_rules! "));
+    // no declaration, the first use will do
+    assert_eq!(scan_name("x + y.x", "x"), Some(0));
+    assert_eq!(scan_name("// x", "x"), None);
+    // an unsaved overlay need not be valid Rust
+    assert_eq!(scan_name("fn a() { let c = '\\é'; }", "x"), None);
+    assert_eq!(scan_name("let c = '\\é'; x", "x"), Some(15));
+}
+
+#[test]
+fn name_position() {
+    #[allow(deprecated)]
+    let symbol = |name: &str, range: ((u32, u32), (u32, u32)), selection: (u32, u32)| {
+        let position = |(line, character)| lsp_types::Position::new(line, character);
+        lsp_types::DocumentSymbol {
+            name: name.to_string(),
+            detail: None,
+            kind: lsp_types::SymbolKind::FUNCTION,
+            tags: None,
+            deprecated: None,
+            range: lsp_types::Range::new(position(range.0), position(range.1)),
+            selection_range: lsp_types::Range::new(
+                position(selection),
+                position((selection.0, selection.1 + name.len() as u32)),
+            ),
+            children: None,
+        }
+    };
+    let mut imp = symbol("Foo", ((0, 0), (3, 1)), (0, 5));
+    imp.children = Some(vec![symbol("new", ((1, 4), (2, 20)), (2, 11))]);
+    let content_modified = crate::mock::Reply::Error {
+        code: lsp_server::ErrorCode::ContentModified as i32,
+        message: "content modified".to_string(),
+    };
+    let (client, mock) = crate::mock::MockServer::new()
+        .initialize(serde_json::json!({ "documentSymbolProvider": true }))
+        .expect_request(
+            "textDocument/documentSymbol",
+            crate::mock::Reply::Result(serde_json::to_value(vec![imp]).unwrap()),
+        )
+        .expect_notification("textDocument/didOpen")
+        .expect_request("textDocument/documentSymbol", content_modified.clone())
+        .expect_request("textDocument/documentSymbol", content_modified.clone())
+        .expect_request("textDocument/documentSymbol", content_modified)
+        .start()
+        .unwrap();
+    let workspace = crate::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+    client.initialize(&workspace).unwrap();
+    let uri = workspace.root_uri().join("src/overlay.rs").unwrap();
+    let location = |start, end| {
+        lsp_types::Location::new(
+            uri.clone(),
+            lsp_types::Range::new(
+                lsp_types::Position::new(start, 0),
+                lsp_types::Position::new(end, 99),
+            ),
+        )
+    };
+    // both from a single documentSymbol
+    let (foo, new) = (location(0, 3), location(1, 2));
+    assert_eq!(
+        client
+            .name_positions([("Foo", &foo), ("new", &new)])
+            .unwrap(),
+        [
+            lsp_types::Position::new(0, 5),
+            lsp_types::Position::new(2, 11)
+        ]
+    );
+
+    // the symbols are stale, found in the open overlay
+    let text = "/// A `λ` is no `ñ`\n#[inline]\npub async fn ñ() {}\n";
+    client
+        .open_document(uri.clone(), "rust", text.to_string())
+        .unwrap();
+    // neither a file that does not exist nor a `untitled:` one can be scanned
+    let missing = lsp_types::Location {
+        uri: workspace.root_uri().join("src/missing.rs").unwrap(),
+        ..location(1, 2)
+    };
+    let untitled = lsp_types::Location {
+        uri: "untitled:Untitled-1".parse().unwrap(),
+        ..location(0, 1)
+    };
+    assert_eq!(
+        client
+            .name_positions([
+                ("ñ", &location(0, 2)),
+                ("f", &missing),
+                ("f", &missing),
+                ("g", &untitled),
+            ])
+            .unwrap(),
+        [
+            lsp_types::Position::new(2, 13),
+            lsp_types::Position::new(1, 0),
+            lsp_types::Position::new(1, 0),
+            lsp_types::Position::new(0, 0),
+        ]
+    );
+    mock.finish().assert_ok();
+}
@@ -0,0 +1,146 @@
+//! `tracing` instrumentation of the JSON-RPC traffic.
+//!
+//! - every request is a `lsp_request` span with its `method`, `id` and
+//!   `params_bytes`, recording `latency_ms` and `outcome` once it is answered,
+//!   timed out or cancelled
+//! - every message is dumped pretty-printed as a TRACE event of target
+//!   `lsp_client::wire`, only serialized when that target is enabled
+//! - every stderr line of a spawned server is an event of target
+//!   `lsp_client::stderr`, at the level of rust-analyzer's `[LEVEL target]` prefix
+
+/// Target of the pretty-printed message dumps.
+const WIRE: &str = "lsp_client::wire";
+
+/// The span following request `req` until its response.
+fn request_span(req: &lsp_server::Request) -> tracing::Span {
+    let span = tracing::debug_span!(
+        "lsp_request",
+        method = %req.method,
+        id = %req.id,
+        params_bytes = tracing::field::Empty,
+        latency_ms = tracing::field::Empty,
+        outcome = tracing::field::Empty,
+    );
+    if !span.is_disabled() {
+        let params_bytes = serde_json::to_vec(&req.params).map_or(0, |params| params.len());
+        span.record("params_bytes", params_bytes);
+    }
+    span
+}
+
+/// Wraps `on_response`, the callback waiting for the response to `req`, so
+/// that it ends the request's span.
+pub(crate) fn on_response(
+    req: &lsp_server::Request,
+    on_response: impl FnOnce(crate::Result<lsp_server::Response>) + Send + 'static,
+) -> crate::router::ResponseCallback {
+    let span = request_span(req);
+    let sent = std::time::Instant::now();
+    Box::new(move |rsp| {
+        response(span, sent, &rsp);
+        on_response(rsp);
+    })
+}
+
+/// Records how the request of `span`, sent at `sent`, ended, and closes the span.
+fn response(
+    span: tracing::Span,
+    sent: std::time::Instant,
+    rsp: &crate::Result<lsp_server::Response>,
+) {
+    let latency_ms = sent.elapsed().as_millis() as u64;
+    let outcome = match rsp {
+        Ok(rsp) => match &rsp.error {
+            None => "ok".to_string(),
+            Some(err) => format!("error {}", err.code),
+        },
+        // given up by the client, no response will be waited for
+        Err(crate::ClientError::Timeout) => "timeout".to_string(),
+        Err(crate::ClientError::Cancelled) => "cancelled".to_string(),
+        Err(err) => err.to_string(),
+    };
+    span.record("latency_ms", latency_ms);
+    span.record("outcome", tracing::field::display(&outcome));
+    tracing::debug!(parent: &span, latency_ms, outcome = %outcome, "response");
+}
+
+pub(crate) fn message(direction: crate::Direction, msg: &lsp_server::Message) {
+    if !tracing::enabled!(target: WIRE, tracing::Level::TRACE) {
+        return;
+    }
+    let pretty = serde_json::to_string_pretty(msg).unwrap_or_default();
+    tracing::trace!(target: WIRE, ?direction, "\n{pretty}");
+}
+
+/// Forwards one stderr line of the server.
+pub(crate) fn stderr_line(line: &str) {
+    let level = line
+        .strip_prefix('[')
+        .and_then(|line| line.split([' ', ']']).next())
+        .unwrap_or_default();
+    match level {
+        "ERROR" => tracing::error!(target: "lsp_client::stderr", "{line}"),
+        "WARN" => tracing::warn!(target: "lsp_client::stderr", "{line}"),
+        "DEBUG" => tracing::debug!(target: "lsp_client::stderr", "{line}"),
+        "TRACE" => tracing::trace!(target: "lsp_client::stderr", "{line}"),
+        _ => tracing::info!(target: "lsp_client::stderr", "{line}"),
+    }
+}
+
+#[test]
+fn trace_requests() {
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let captured = Captured::default();
+    let writer = captured.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::TRACE)
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_ansi(false)
+        .with_writer(move || writer.clone())
+        .finish();
+    tracing::subscriber::with_default(subscriber, || {
+        let (client, mock) = crate::mock::MockServer::new()
+            .expect_request("mock/ok", crate::mock::Reply::Result(serde_json::json!(1)))
+            .expect_request(
+                "mock/fail",
+                crate::mock::Reply::Error {
+                    code: lsp_server::ErrorCode::InternalError as i32,
+                    message: "failed".to_string(),
+                },
+            )
+            .hold_request("mock/slow")
+            .expect_notification("$/cancelRequest")
+            .hold_request("mock/dropped")
+            .expect_notification("$/cancelRequest")
+            .start()
+            .unwrap();
+        let req =
+            |method: &str| lsp_server::Request::new(client.next_id(), method.to_string(), [0; 8]);
+        client.send_req(req("mock/ok")).unwrap();
+        client.send_req(req("mock/fail")).unwrap_err();
+        let slow = client.send(req("mock/slow")).unwrap();
+        let timeout = std::time::Duration::from_millis(10);
+        slow.wait_timeout(timeout).unwrap_err();
+        client.send(req("mock/dropped")).unwrap().cancel().unwrap();
+        mock.finish().assert_ok();
+    });
+
+    let captured = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+    assert!(captured.contains("method=mock/ok"), "{captured}");
+    assert!(captured.contains("params_bytes=17"), "{captured}");
+    assert!(captured.contains("outcome=ok"), "{captured}");
+    assert!(captured.contains("outcome=error -32603"), "{captured}");
+    assert!(captured.contains("outcome=timeout"), "{captured}");
+    assert!(captured.contains("outcome=cancelled"), "{captured}");
+    assert!(captured.contains("\"method\": \"mock/fail\""), "{captured}");
+}
@@ -0,0 +1,142 @@
+//! JSONL transcripts of the messages exchanged with a server, recorded by
+//! [`Client::set_transcript`](crate::Client::set_transcript) or
+//! `AsyncClient::set_transcript` and played back by
+//! [`MockServer::replay`](crate::mock::MockServer::replay).
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Direction {
+    ToServer,
+    FromServer,
+}
+
+/// One line of a transcript.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TranscriptEntry {
+    /// milliseconds since the Unix epoch
+    pub timestamp_ms: u64,
+    pub direction: Direction,
+    pub message: lsp_server::Message,
+}
+
+/// Reads a transcript, one [`TranscriptEntry`] per line.
+pub fn read_transcript(transcript: impl std::io::BufRead) -> crate::Result<Vec<TranscriptEntry>> {
+    let mut entries = Vec::new();
+    for line in transcript.lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            entries.push(serde_json::from_str(&line)?);
+        }
+    }
+    Ok(entries)
+}
+
+/// Records `message` to the transcript if one is set, which is dropped on the
+/// first write error.
+pub(crate) fn record(
+    transcript: &std::sync::Mutex<Option<Recorder>>,
+    direction: Direction,
+    message: &lsp_server::Message,
+) {
+    let mut transcript = transcript.lock().unwrap();
+    if let Some(recorder) = transcript.as_mut() {
+        if let Err(err) = recorder.record(direction, message) {
+            tracing::warn!("stopped recording the transcript: {err}");
+            *transcript = None;
+        }
+    }
+}
+
+/// Appends every message to a transcript as it is sent or received.
+pub(crate) struct Recorder(Box<dyn std::io::Write + Send>);
+
+impl Recorder {
+    pub(crate) fn new(transcript: impl std::io::Write + Send + 'static) -> Self {
+        Self(Box::new(transcript))
+    }
+
+    pub(crate) fn record(
+        &mut self,
+        direction: Direction,
+        message: &lsp_server::Message,
+    ) -> std::io::Result<()> {
+        #[derive(serde::Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Entry<'a> {
+            timestamp_ms: u64,
+            direction: Direction,
+            message: &'a lsp_server::Message,
+        }
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_millis() as u64);
+        let entry = Entry {
+            timestamp_ms,
+            direction,
+            message,
+        };
+        serde_json::to_writer(&mut self.0, &entry)?;
+        // flushed line by line, so the transcript of a crashed session is complete
+        self.0.write_all(b"\n")?;
+        self.0.flush()
+    }
+}
+
+#[test]
+fn record_and_replay() {
+    let path = std::env::temp_dir().join(format!(
+        "lsp_client_transcript_{}.jsonl",
+        std::process::id()
+    ));
+    let hover = |client: &crate::Client| {
+        let workspace = crate::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+        client.init(&workspace).unwrap();
+        let uri = workspace.root_uri().join("src/lib.rs").unwrap();
+        let hover = client
+            .request::<lsp_types::request::HoverRequest>(lsp_types::HoverParams {
+                text_document_position_params: lsp_types::TextDocumentPositionParams::new(
+                    lsp_types::TextDocumentIdentifier::new(uri),
+                    lsp_types::Position::new(0, 0),
+                ),
+                work_done_progress_params: Default::default(),
+            })
+            .unwrap();
+        client.exit().unwrap();
+        hover.unwrap().contents
+    };
+
+    let (mut client, mock) = crate::mock::MockServer::new()
+        .initialize(serde_json::json!({ "hoverProvider": true }))
+        .notify(
+            "window/logMessage",
+            serde_json::json!({ "type": 3, "message": "loaded" }),
+        )
+        .expect_request(
+            "textDocument/hover",
+            crate::mock::Reply::Result(serde_json::json!({ "contents": "recorded" })),
+        )
+        .shutdown()
+        .start()
+        .unwrap();
+    client.set_transcript(std::fs::File::create(&path).unwrap());
+    let recorded = hover(&client);
+    mock.finish().assert_ok();
+
+    let transcript = std::fs::File::open(&path).unwrap();
+    let entries = read_transcript(std::io::BufReader::new(transcript)).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(entries.first().unwrap().direction, Direction::ToServer);
+    let mut log_messages = entries.iter().filter(|entry| match &entry.message {
+        lsp_server::Message::Notification(notif) => notif.method == "window/logMessage",
+        _ => false,
+    });
+    assert_eq!(
+        log_messages.next().unwrap().direction,
+        Direction::FromServer
+    );
+
+    let (client, replay) = crate::mock::MockServer::replay(entries).start().unwrap();
+    assert_eq!(hover(&client), recorded);
+    replay.finish().assert_ok();
+}
@@ -0,0 +1,160 @@
+//! How [`Client`](crate::Client) exchanges messages with the server:
+//! `Content-Length` framed JSON-RPC over a child's stdio, a socket or any
+//! stream pair, or unframed over the channels of an in-process server.
+
+/// The client's end of the connection carrying messages to the server.
+pub(crate) enum MessageWriter {
+    Stream(Box<dyn std::io::Write + Send>),
+    Channel(crossbeam_channel::Sender<lsp_server::Message>),
+    /// dropped by the client, an in-process server sees its receiver disconnect
+    Closed,
+}
+
+/// The client's end of the connection carrying messages from the server.
+pub(crate) enum MessageReader {
+    Stream(std::io::BufReader<Box<dyn std::io::Read + Send>>),
+    Channel(crossbeam_channel::Receiver<lsp_server::Message>),
+}
+
+impl MessageWriter {
+    pub(crate) fn new(to_server: impl std::io::Write + Send + 'static) -> Self {
+        Self::Stream(Box::new(to_server))
+    }
+
+    pub(crate) fn write(&mut self, msg: lsp_server::Message) -> std::io::Result<()> {
+        match self {
+            Self::Stream(to_server) => msg.write(to_server),
+            // the server thread returned and dropped its receiver
+            Self::Channel(to_server) => to_server
+                .send(msg)
+                .map_err(|_| std::io::ErrorKind::BrokenPipe.into()),
+            Self::Closed => Err(std::io::ErrorKind::BrokenPipe.into()),
+        }
+    }
+
+    pub(crate) fn close(&mut self) {
+        *self = Self::Closed;
+    }
+}
+
+impl MessageReader {
+    pub(crate) fn new(from_server: impl std::io::Read + Send + 'static) -> Self {
+        Self::Stream(std::io::BufReader::new(Box::new(from_server)))
+    }
+
+    /// The next message, `None` once the server closed the connection.
+    pub(crate) fn read(&mut self) -> std::io::Result<Option<lsp_server::Message>> {
+        match self {
+            // alternative lsp reader stream parsing https://github.com/rust-lang/rls/blob/master/rls/src/server/io.rs#L40
+            Self::Stream(from_server) => lsp_server::Message::read(from_server),
+            Self::Channel(from_server) => Ok(from_server.recv().ok()),
+        }
+    }
+}
+
+/// The client's half of `lsp_server::Connection::memory()`, no framing involved.
+pub(crate) fn memory(conn: lsp_server::Connection) -> (MessageWriter, MessageReader) {
+    (
+        MessageWriter::Channel(conn.sender),
+        MessageReader::Channel(conn.receiver),
+    )
+}
+
+/// Both directions of a socket, plus a hook shutting it down so the reader
+/// thread blocked on it returns when the client is dropped.
+pub(crate) type SocketParts = (MessageWriter, MessageReader, Box<dyn Fn() + Send + Sync>);
+
+pub(crate) fn tcp(addr: impl std::net::ToSocketAddrs) -> crate::Result<SocketParts> {
+    let stream = std::net::TcpStream::connect(addr)?;
+    // every message is flushed whole, don't hold small ones back
+    stream.set_nodelay(true)?;
+    let reader = stream.try_clone()?;
+    let closer = stream.try_clone()?;
+    Ok((
+        MessageWriter::new(stream),
+        MessageReader::new(reader),
+        Box::new(move || {
+            let _ = closer.shutdown(std::net::Shutdown::Both);
+        }),
+    ))
+}
+
+#[cfg(unix)]
+pub(crate) fn unix(path: &std::path::Path) -> crate::Result<SocketParts> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    let reader = stream.try_clone()?;
+    let closer = stream.try_clone()?;
+    Ok((
+        MessageWriter::new(stream),
+        MessageReader::new(reader),
+        Box::new(move || {
+            let _ = closer.shutdown(std::net::Shutdown::Both);
+        }),
+    ))
+}
+
+#[test]
+fn tcp_round_trip() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = std::io::BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let req = match lsp_server::Message::read(&mut reader).unwrap() {
+            Some(lsp_server::Message::Request(req)) => req,
+            msg => panic!("expected a request, got {msg:?}"),
+        };
+        lsp_server::Message::from(lsp_server::Response::new_ok(req.id, req.params))
+            .write(&mut writer)
+            .unwrap();
+        req.method
+    });
+    let client = crate::Client::connect_tcp(addr).unwrap();
+    let echoed = client
+        .send_req(lsp_server::Request::new(
+            client.next_id(),
+            "echo".to_string(),
+            serde_json::json!({ "text": "é" }),
+        ))
+        .unwrap();
+    assert_eq!(echoed, Some(serde_json::json!({ "text": "é" })));
+    assert_eq!(server.join().unwrap(), "echo");
+    drop(client);
+}
+
+#[test]
+fn in_process_server() {
+    let mut client = crate::Client::in_process(|conn| {
+        let capabilities = serde_json::json!({ "hoverProvider": true });
+        conn.initialize(capabilities).unwrap();
+        for msg in &conn.receiver {
+            if let lsp_server::Message::Request(req) = msg {
+                if conn.handle_shutdown(&req).unwrap() {
+                    return;
+                }
+                let hover = serde_json::json!({ "contents": "fn main()" });
+                let rsp = lsp_server::Response::new_ok(req.id, hover);
+                conn.sender.send(rsp.into()).unwrap();
+            }
+        }
+    })
+    .unwrap();
+    client.set_profile(crate::GenericProfile::new("in-process", [] as [&str; 0]));
+    let workspace = crate::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+    client.init(&workspace).unwrap();
+    assert!(client.supports("textDocument/hover"));
+    assert!(!client.supports("textDocument/definition"));
+    let position = lsp_types::TextDocumentPositionParams::new(
+        lsp_types::TextDocumentIdentifier::new(workspace.root_uri().join("src/lib.rs").unwrap()),
+        lsp_types::Position::new(0, 0),
+    );
+    let hover = client
+        .request::<lsp_types::request::HoverRequest>(lsp_types::HoverParams {
+            text_document_position_params: position,
+            work_done_progress_params: Default::default(),
+        })
+        .unwrap();
+    assert!(hover.is_some());
+    assert_eq!(client.exit().unwrap(), None);
+}
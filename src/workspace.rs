@@ -0,0 +1,101 @@
+/// The workspace folders sent to the server in `initialize`; the first one is the root.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Workspace {
+    folders: Vec<lsp_types::WorkspaceFolder>,
+}
+
+impl Workspace {
+    /// A workspace rooted at `root`, any existing directory.
+    pub fn new(root: impl AsRef<std::path::Path>) -> crate::Result<Self> {
+        Ok(Self {
+            folders: vec![workspace_folder(root.as_ref())?],
+        })
+    }
+
+    /// The workspace of the nearest Cargo project containing `dir`, see [`find_cargo_workspace`].
+    pub fn discover(dir: impl AsRef<std::path::Path>) -> crate::Result<Self> {
+        Self::new(find_cargo_workspace(dir.as_ref())?)
+    }
+
+    /// Adds another workspace folder, e.g. a second Cargo workspace of a monorepo.
+    pub fn add_folder(mut self, dir: impl AsRef<std::path::Path>) -> crate::Result<Self> {
+        let folder = workspace_folder(dir.as_ref())?;
+        if !self.folders.contains(&folder) {
+            self.folders.push(folder);
+        }
+        Ok(self)
+    }
+
+    pub fn root_uri(&self) -> &lsp_types::Url {
+        &self.folders[0].uri
+    }
+
+    pub fn folders(&self) -> &[lsp_types::WorkspaceFolder] {
+        &self.folders
+    }
+}
+
+fn workspace_folder(dir: &std::path::Path) -> crate::Result<lsp_types::WorkspaceFolder> {
+    let dir = dir.canonicalize()?;
+    let uri = lsp_types::Url::from_file_path(&dir)
+        .map_err(|()| crate::ClientError::InvalidPath(dir.clone()))?;
+    let name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| dir.display().to_string());
+    Ok(lsp_types::WorkspaceFolder { uri, name })
+}
+
+/// Walks up from `dir` to the nearest `Cargo.toml` declaring `[workspace]`,
+/// falling back to the nearest package `Cargo.toml` outside of any workspace.
+pub fn find_cargo_workspace(dir: &std::path::Path) -> crate::Result<std::path::PathBuf> {
+    let dir = dir.canonicalize()?;
+    let mut package_root = None;
+    for ancestor in dir.ancestors() {
+        let manifest = ancestor.join("Cargo.toml");
+        let manifest = match std::fs::read_to_string(&manifest) {
+            Ok(manifest) => manifest,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        if manifest.lines().any(|line| {
+            let line = line.trim();
+            line == "[workspace]" || line.starts_with("[workspace.")
+        }) {
+            return Ok(ancestor.to_path_buf());
+        }
+        package_root.get_or_insert_with(|| ancestor.to_path_buf());
+    }
+    package_root.ok_or(crate::ClientError::NoCargoWorkspace(dir))
+}
+
+#[test]
+fn discover_cargo_workspace() {
+    let tmp = std::env::temp_dir().join(format!("lsp_client_workspace_{}", std::process::id()));
+    let member = tmp.join("crates/member/src");
+    std::fs::create_dir_all(&member).unwrap();
+    std::fs::write(
+        tmp.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"crates/member\"]\n",
+    )
+    .unwrap();
+    std::fs::write(
+        tmp.join("crates/member/Cargo.toml"),
+        "[package]\nname = \"member\"\n",
+    )
+    .unwrap();
+
+    let root = tmp.canonicalize().unwrap();
+    assert_eq!(find_cargo_workspace(&member).unwrap(), root);
+    let workspace = Workspace::discover(&member)
+        .unwrap()
+        .add_folder(tmp.join("crates/member"))
+        .unwrap();
+    assert_eq!(
+        workspace.root_uri(),
+        &lsp_types::Url::from_file_path(&root).unwrap()
+    );
+    assert_eq!(workspace.folders()[1].name, "member");
+
+    std::fs::remove_dir_all(&tmp).unwrap();
+}
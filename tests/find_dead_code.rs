@@ -0,0 +1,94 @@
+/*
+dead_code sample:
+```
+[workspace]
+members = [
+    "crates/callee",
+    "crates/pub_util",
+]
+
+cat crates/pub_util/src/lib.rs
+pub fn used_pub() {}
+pub fn unused_pub() {}
+
+cat crates/callee/src/main.rs
+fn main() {
+    pub_util::used_pub();
+}
+```
+*/
+#[test]
+fn find_dead_code_in_cargo_workspace() {
+    // rust-analyzer's RA_LOG output arrives as `lsp_client::stderr` events
+    let _ = tracing_subscriber::fmt().with_test_writer().try_init();
+    let profile = lsp_client::RustAnalyzerProfile::default().env("RA_LOG", "rust_analyzer=info");
+    let lsp_client = lsp_client::Client::spawn_profile(profile).unwrap();
+    /* LSP server init */
+    let workspace = std::env::var("LSP_CLIENT_TEST_WORKSPACE")
+        .unwrap_or_else(|_| "/home/w/repos/temp/unused_pub_test_case".to_string());
+    let workspace = lsp_client::Workspace::new(workspace).unwrap();
+    lsp_client.init(&workspace).unwrap();
+
+    /* LSP server enter main loop */
+    let workspace_symbol_rsp = lsp_client
+        .request::<lsp_client::ra_ext::WorkspaceSymbol>(lsp_client::ra_ext::WorkspaceSymbolParams {
+            search_kind: Some(lsp_client::ra_ext::WorkspaceSymbolSearchKind::AllSymbols),
+            work_done_progress_params: lsp_types::WorkDoneProgressParams {
+                work_done_token: Some(lsp_types::ProgressToken::String(
+                    "workspace_symbol".to_string(),
+                )),
+            },
+            ..Default::default()
+        })
+        .unwrap();
+    let symbols: Vec<_> = workspace_symbol_rsp
+        .unwrap()
+        .into_iter()
+        .filter(|symbol| symbol.kind == lsp_types::SymbolKind::FUNCTION && symbol.name != "main")
+        .collect();
+    // every file is asked for its symbols once
+    let positions = lsp_client
+        .name_positions(
+            symbols
+                .iter()
+                .map(|symbol| (symbol.name.as_str(), &symbol.location)),
+        )
+        .unwrap();
+    // pipeline every references query, then collect the responses
+    let mut find_refs = Vec::new();
+    for (symbol, position) in symbols.into_iter().zip(positions) {
+        let pending = lsp_client
+            .send_request::<lsp_types::request::References>(lsp_types::ReferenceParams {
+                text_document_position: lsp_types::TextDocumentPositionParams {
+                    text_document: lsp_types::TextDocumentIdentifier {
+                        uri: symbol.location.uri.clone(),
+                    },
+                    position,
+                },
+                work_done_progress_params: lsp_types::WorkDoneProgressParams::default(),
+                partial_result_params: lsp_types::PartialResultParams::default(),
+                context: lsp_types::ReferenceContext {
+                    include_declaration: false,
+                },
+            })
+            .unwrap();
+        find_refs.push((symbol, pending));
+    }
+    for (symbol, pending) in find_refs {
+        let path = symbol.location.uri.to_string();
+        let refs = match pending.wait().unwrap() {
+            Some(refs) => refs,
+            None => {
+                println!("References return None");
+                continue;
+            }
+        };
+        if refs.is_empty() {
+            eprintln!("dead_code found {path} {}", symbol.name);
+        }
+    }
+
+    /* LSP server exit */
+    let exit_status = lsp_client.exit().unwrap();
+    assert!(exit_status.unwrap().success());
+}
@@ -0,0 +1,241 @@
+// the lsp-mock binary plays a script saved as JSON to a client that spawned it
+#[test]
+fn spawn_mock_server() {
+    let dir = std::env::temp_dir().join(format!("lsp_client_mock_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.json");
+    let report = dir.join("report.json");
+    let mock = lsp_client::mock::MockServer::new()
+        .initialize(serde_json::json!({}))
+        .notify(
+            "window/showMessage",
+            serde_json::json!({ "type": 3, "message": "hello" }),
+        )
+        .shutdown();
+    std::fs::write(&script, serde_json::to_vec(&mock).unwrap()).unwrap();
+
+    let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_lsp-mock"));
+    command.arg(&script).arg(&report);
+    let mut client = lsp_client::Client::spawn(command).unwrap();
+    client.set_profile(lsp_client::GenericProfile::new("lsp-mock", [] as [&str; 0]));
+    let messages = client.subscribe("window/showMessage");
+    client
+        .init(&lsp_client::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap())
+        .unwrap();
+    assert_eq!(messages.recv().unwrap().params["message"], "hello");
+    assert!(client.exit().unwrap().unwrap().success());
+
+    let report: lsp_client::mock::MockReport =
+        serde_json::from_slice(&std::fs::read(&report).unwrap()).unwrap();
+    report.assert_ok();
+    assert_eq!(report.notifications("exit").len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// a supervised lsp-mock crashes with a request in flight, the next one hangs
+// in the handshake and is killed, the third gets the documents and the request
+#[test]
+fn restart_crashed_mock_server() {
+    let dir = std::env::temp_dir().join(format!("lsp_client_restart_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let scripts = [
+        lsp_client::mock::MockServer::new()
+            .initialize(serde_json::json!({ "hoverProvider": true }))
+            .expect_notification("textDocument/didOpen")
+            .hold_request("textDocument/hover")
+            .hang_up(),
+        lsp_client::mock::MockServer::new()
+            .hold_request("initialize")
+            .sleep(std::time::Duration::from_secs(10)),
+        lsp_client::mock::MockServer::new()
+            .initialize(serde_json::json!({ "hoverProvider": true }))
+            .expect_notification("textDocument/didOpen")
+            .expect_request(
+                "textDocument/hover",
+                lsp_client::mock::Reply::Result(serde_json::json!({ "contents": "fn main()" })),
+            )
+            .shutdown(),
+    ];
+    for (i, mock) in scripts.iter().enumerate() {
+        std::fs::write(
+            dir.join(format!("{i}.json")),
+            serde_json::to_vec(mock).unwrap(),
+        )
+        .unwrap();
+    }
+    let report = |i: usize| -> lsp_client::mock::MockReport {
+        serde_json::from_slice(&std::fs::read(dir.join(format!("{i}.report.json"))).unwrap())
+            .unwrap()
+    };
+
+    let mut spawned = 0;
+    let script_dir = dir.clone();
+    let make_command = move || {
+        let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_lsp-mock"));
+        command
+            .arg(script_dir.join(format!("{spawned}.json")))
+            .arg(script_dir.join(format!("{spawned}.report.json")));
+        spawned += 1;
+        command
+    };
+    let policy = lsp_client::RestartPolicy {
+        retry_in_flight: true,
+        handshake_timeout: std::time::Duration::from_millis(500),
+        ..Default::default()
+    };
+    let mut client = lsp_client::Client::spawn_supervised(
+        make_command,
+        policy,
+        lsp_client::StderrPolicy::default(),
+    )
+    .unwrap();
+    client.set_profile(lsp_client::GenericProfile::new("lsp-mock", [] as [&str; 0]));
+    let workspace = lsp_client::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+    client.initialize(&workspace).unwrap();
+    let uri = workspace.root_uri().join("src/lib.rs").unwrap();
+    client
+        .open_document(uri.clone(), "rust", "fn main() {}".to_string())
+        .unwrap();
+    let hover = lsp_types::HoverParams {
+        text_document_position_params: lsp_types::TextDocumentPositionParams::new(
+            lsp_types::TextDocumentIdentifier::new(uri),
+            lsp_types::Position::new(0, 0),
+        ),
+        work_done_progress_params: Default::default(),
+    };
+    let hovered = client
+        .send_request::<lsp_types::request::HoverRequest>(hover)
+        .unwrap();
+    assert_eq!(
+        hovered.wait().unwrap().unwrap().contents,
+        lsp_types::HoverContents::Scalar(lsp_types::MarkedString::String("fn main()".to_string()))
+    );
+    assert_eq!(client.restarts(), 2);
+    assert!(client.exit().unwrap().unwrap().success());
+
+    report(0).assert_ok();
+    // killed in the handshake, it never wrote a report
+    assert!(!dir.join("1.report.json").exists());
+    let restarted = report(2);
+    restarted.assert_ok();
+    assert_eq!(
+        restarted.requests("initialize")[0].params,
+        report(0).requests("initialize")[0].params
+    );
+    assert_eq!(
+        restarted.notifications("textDocument/didOpen")[0].params["textDocument"]["text"],
+        "fn main() {}"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// the async client against lsp-mock: timeouts, notifications, shutdown, and a crash
+#[cfg(feature = "tokio")]
+#[test]
+fn async_client_against_mock_server() {
+    let dir = std::env::temp_dir().join(format!("lsp_client_async_mock_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let spawn = |name: &str, mock: lsp_client::mock::MockServer| {
+        let script = dir.join(format!("{name}.json"));
+        std::fs::write(&script, serde_json::to_vec(&mock).unwrap()).unwrap();
+        let mut command = tokio::process::Command::new(env!("CARGO_BIN_EXE_lsp-mock"));
+        command
+            .arg(&script)
+            .arg(dir.join(format!("{name}.report.json")));
+        let mut client = lsp_client::AsyncClient::spawn(command).unwrap();
+        client.set_profile(lsp_client::GenericProfile::new("lsp-mock", [] as [&str; 0]));
+        client
+    };
+    let report = |name: &str| -> lsp_client::mock::MockReport {
+        serde_json::from_slice(&std::fs::read(dir.join(format!("{name}.report.json"))).unwrap())
+            .unwrap()
+    };
+    let workspace = lsp_client::Workspace::new(env!("CARGO_MANIFEST_DIR")).unwrap();
+    let position = lsp_types::TextDocumentPositionParams::new(
+        lsp_types::TextDocumentIdentifier::new(workspace.root_uri().join("src/lib.rs").unwrap()),
+        lsp_types::Position::new(0, 0),
+    );
+    let hover = || lsp_types::HoverParams {
+        text_document_position_params: position.clone(),
+        work_done_progress_params: Default::default(),
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let served = lsp_client::mock::MockServer::new()
+        .initialize(serde_json::json!({ "hoverProvider": true }))
+        .hold_request("textDocument/hover")
+        .expect_notification("$/cancelRequest")
+        .notify(
+            "window/showMessage",
+            serde_json::json!({ "type": 3, "message": "hello" }),
+        )
+        .expect_request(
+            "textDocument/hover",
+            lsp_client::mock::Reply::Result(serde_json::json!({ "contents": "fn main()" })),
+        )
+        .shutdown();
+    runtime.block_on(async {
+        let mut client = spawn("served", served);
+        client.set_transcript(std::fs::File::create(dir.join("served.jsonl")).unwrap());
+        let mut messages = client.subscribe("window/showMessage");
+        client.init(&workspace).await.unwrap();
+        let timeout = std::time::Duration::from_millis(50);
+        assert!(matches!(
+            client
+                .request_timeout::<lsp_types::request::HoverRequest>(hover(), timeout)
+                .await,
+            Err(lsp_client::ClientError::Timeout)
+        ));
+        let message = std::future::poll_fn(|cx| {
+            futures_core::Stream::poll_next(std::pin::Pin::new(&mut messages), cx)
+        })
+        .await;
+        assert_eq!(message.unwrap().params["message"], "hello");
+        let hovered = client
+            .request::<lsp_types::request::HoverRequest>(hover())
+            .await
+            .unwrap();
+        assert!(hovered.is_some());
+        assert!(client.shutdown().await.unwrap().success());
+    });
+    let served = report("served");
+    served.assert_ok();
+    let transcript = std::fs::File::open(dir.join("served.jsonl")).unwrap();
+    let transcript = lsp_client::read_transcript(std::io::BufReader::new(transcript)).unwrap();
+    assert!(matches!(
+        &transcript[0].message,
+        lsp_server::Message::Request(req) if req.method == "initialize"
+    ));
+    assert!(transcript.iter().any(|entry| {
+        entry.direction == lsp_client::Direction::FromServer
+            && matches!(&entry.message, lsp_server::Message::Notification(notif) if notif.method == "window/showMessage")
+    }));
+    let hover_id = serde_json::to_value(&served.requests("textDocument/hover")[0].id).unwrap();
+    assert_eq!(
+        served.notifications("$/cancelRequest")[0].params["id"],
+        hover_id
+    );
+
+    let crashed = lsp_client::mock::MockServer::new()
+        .initialize(serde_json::json!({ "hoverProvider": true }))
+        .hold_request("textDocument/hover")
+        .hang_up();
+    runtime.block_on(async {
+        let client = spawn("crashed", crashed);
+        client.init(&workspace).await.unwrap();
+        match client
+            .request::<lsp_types::request::HoverRequest>(hover())
+            .await
+        {
+            Err(lsp_client::ClientError::ServerDied { status, .. }) => {
+                assert!(status.unwrap().success())
+            }
+            rsp => panic!("{rsp:?}"),
+        }
+    });
+    report("crashed").assert_ok();
+    std::fs::remove_dir_all(&dir).unwrap();
+}